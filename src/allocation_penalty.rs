@@ -1,212 +1,517 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
 
-use crate::problem::{Cottages, Reservations};
+use crate::{
+    amenity::AmenitySet,
+    problem::{Cottages, Problem, Reservations},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Copy)]
 pub enum Penalty {
     Free,
-    Upgrade,
+    // How far the cottage is above what was booked:
+    // class steps plus capacity steps, counting only capacities that exist
+    Upgrade(usize),
     Impossible,
 }
 
+impl Penalty {
+    // Penalty of an allocation that must pass both checks
+    fn combine(self, other: Self) -> Self {
+        match (self, other) {
+            (Penalty::Impossible, _) | (_, Penalty::Impossible) => Penalty::Impossible,
+            (Penalty::Upgrade(a), Penalty::Upgrade(b)) => Penalty::Upgrade(a + b),
+            (Penalty::Upgrade(a), Penalty::Free) | (Penalty::Free, Penalty::Upgrade(a)) => {
+                Penalty::Upgrade(a)
+            }
+            (Penalty::Free, Penalty::Free) => Penalty::Free,
+        }
+    }
+
+    pub fn upgrade_cost(&self) -> usize {
+        match self {
+            Penalty::Upgrade(cost) => *cost,
+            _ => 0,
+        }
+    }
+}
+
+// Class and size of both sides, enough to price any pair
+// without storing a penalty per pair
+#[derive(Debug, Clone, Default)]
+struct Upgrades {
+    cottage_class: Vec<usize>,
+    cottage_capacity: Vec<usize>,
+    reservation_class: Vec<usize>,
+    reservation_people: Vec<usize>,
+    // Distinct capacities, sorted
+    capacities: Vec<usize>,
+}
+
+impl Upgrades {
+    fn new(cottages: &Cottages, reservations: &Reservations) -> Self {
+        let mut capacities = cottages.capacity.clone();
+        capacities.sort_unstable();
+        capacities.dedup();
+
+        Self {
+            cottage_class: cottages.class.clone(),
+            cottage_capacity: cottages.capacity.clone(),
+            reservation_class: reservations.class.clone(),
+            reservation_people: reservations.people.clone(),
+            capacities,
+        }
+    }
+
+    fn level(&self, people: usize) -> usize {
+        self.capacities
+            .partition_point(|&capacity| capacity < people)
+    }
+
+    fn penalty(&self, cottage: usize, reservation: usize) -> Penalty {
+        // Class mismatch
+        let reservation_class = self.reservation_class[reservation];
+        let cottage_class = self.cottage_class[cottage];
+        let class = match reservation_class.cmp(&cottage_class) {
+            std::cmp::Ordering::Less => Penalty::Upgrade(cottage_class - reservation_class),
+            std::cmp::Ordering::Equal => Penalty::Free,
+            std::cmp::Ordering::Greater => Penalty::Impossible,
+        };
+
+        // Size mismatch, in steps between the capacities present,
+        // so a 2 -> 4 upgrade costs the same as 8 -> 12 if no 10 exists
+        let reservation_people = self.reservation_people[reservation];
+        let cottage_people = self.cottage_capacity[cottage];
+        let size = match reservation_people.cmp(&cottage_people) {
+            std::cmp::Ordering::Less => Penalty::Upgrade(
+                (self.level(cottage_people) - self.level(reservation_people)).max(1),
+            ),
+            std::cmp::Ordering::Equal => Penalty::Free,
+            std::cmp::Ordering::Greater => Penalty::Impossible,
+        };
+
+        class.combine(size)
+    }
+}
+
+// Distinct target sets on a day beyond which groups are only formed from identical sets
+const MAX_TARGET_SETS: usize = 256;
+
+// Reservations around a day that can only use fewer cottages between them
+// than there are of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crowd {
+    pub day: usize,
+    pub reservations: Vec<usize>,
+    pub cottages: Vec<usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct AllocationPenalty {
-    // reservations x cottages
-    // For each reservation/cottage pair,
-    // what is the penalty for this allocation?
-    pub allocation_penalty: Vec<Penalty>,
+    // For each reservation the cottages it can be allocated to, sorted.
+    // Those of reservation r are `targets[offsets[r]..offsets[r + 1]]`,
+    // any other pair is impossible.
+    // Cottages are stored as u32, there can be a pair for most of reservations x cottages
+    offsets: Vec<usize>,
+    targets: Vec<u32>,
+    // Upgrade cost of each target, 0 for free
+    upgrade_costs: Vec<u32>,
 
     pub reallocatable: Vec<bool>,
     pub reallocatable_reservations: Vec<usize>,
 
-    pub possible_targets: Vec<Vec<usize>>,
     pub priority: Vec<f64>,
 
+    // Found while propagating, each means not every reservation can be placed
+    pub crowds: Vec<Crowd>,
+
     pub cottages: usize,
     pub reservations: usize,
 }
 
 impl AllocationPenalty {
-    pub fn get_penalty_arr(&self, reservation: usize) -> &[Penalty] {
-        #[cfg(feature = "supersafe")]
-        assert!((reservation + 1) * self.cottages <= self.allocation_penalty.len());
-        unsafe {
-            &self
-                .allocation_penalty
-                .get_unchecked(reservation * self.cottages..(reservation + 1) * self.cottages)
-        }
+    // Penalty for every cottage, impossible ones included
+    pub fn get_penalty_arr(&self, reservation: usize) -> Vec<Penalty> {
+        (0..self.cottages)
+            .map(|cottage| self.get(cottage, reservation))
+            .collect()
     }
 
     pub fn get(&self, cottage: usize, reservation: usize) -> Penalty {
-        #[cfg(feature = "supersafe")]
-        assert!((reservation) * self.cottages + cottage < self.allocation_penalty.len());
-        unsafe { *self.get_penalty_arr(reservation).get_unchecked(cottage) }
+        match self
+            .possible_targets(reservation)
+            .binary_search(&(cottage as u32))
+        {
+            Ok(i) => match self.upgrade_costs[self.offsets[reservation] + i] {
+                0 => Penalty::Free,
+                cost => Penalty::Upgrade(cost as usize),
+            },
+            Err(_) => Penalty::Impossible,
+        }
     }
 
-    pub fn possible_targets(&self, reservation: usize) -> &[usize] {
+    pub fn possible_targets(&self, reservation: usize) -> &[u32] {
         #[cfg(feature = "supersafe")]
-        assert!(self.possible_targets.get(reservation).is_some());
+        assert!(reservation + 1 < self.offsets.len());
 
-        unsafe { &self.possible_targets.get_unchecked(reservation)[..] }
-    }
-
-    fn possible_targets_iter(&self, reservation: usize) -> impl Iterator<Item = usize> + '_ {
-        self.get_penalty_arr(reservation)
-            .iter()
-            .enumerate()
-            .filter(|x| x.1 != &Penalty::Impossible)
-            .map(|x| x.0)
+        unsafe {
+            let start = *self.offsets.get_unchecked(reservation);
+            let end = *self.offsets.get_unchecked(reservation + 1);
+            self.targets.get_unchecked(start..end)
+        }
     }
 
-    fn calculate_possible_targets(&mut self) {
-        for reservation in 0..self.reallocatable.len() {
-            let v: Vec<_> = self.possible_targets_iter(reservation).collect();
-            self.possible_targets.push(v);
-        }
+    // Possible reservation/cottage pairs over all reservations
+    pub fn possible_pairs(&self) -> usize {
+        self.targets.len()
     }
 
+    // How much of its cottages a reservation has to share: the fraction of its targets
+    // that another reservation staying at the same time can use as well,
+    // averaged over those with any target.
+    // Summed over its targets, the others that can use a cottage during a stay are
+    // those that arrive before it ends, less those that left by the time it starts.
+    // Both are counted per cottage in a sweep over the days, without visiting each overlap.
     fn calculate_priority(&mut self, reservations: &Reservations) {
-        let mut priority = vec![0f64; reservations.reservations()];
-
-        let mut possible_targets_hash = vec![HashSet::new(); reservations.reservations()];
-        for reservation in 0..reservations.reservations() {
-            let possible_target_hash: HashSet<_> =
-                HashSet::from_iter(self.possible_targets[reservation].iter());
-            possible_targets_hash[reservation] = possible_target_hash;
-        }
-
-        for reservation in 0..reservations.reservations() {
-            let reservation_targets = &possible_targets_hash[reservation];
+        // Stays of no days overlap nothing
+        let staying: Vec<usize> = (0..self.reservations)
+            .filter(|&x| !self.possible_targets(x).is_empty() && !reservations.range(x).is_empty())
+            .collect();
 
-            let mut actual_overlaps = 0;
-            for overlap in reservations.get_overlaps(reservation) {
-                let overlap_possible_targets = &possible_targets_hash[overlap];
-                let intersection = overlap_possible_targets.intersection(&reservation_targets);
-                let count = intersection.count();
+        // Per cottage, and of all reservations with a target at the end
+        let all = self.cottages;
+        let mut counts = vec![0u32; self.cottages + 1];
+        let count = |counts: &mut Vec<u32>, reservation: usize| {
+            for cottage in self
+                .possible_targets(reservation)
+                .iter()
+                .map(|&x| x as usize)
+            {
+                counts[cottage] += 1;
+            }
+            counts[all] += 1;
+        };
+        let sum = |counts: &[u32], reservation: usize| -> (u64, u64) {
+            let shared = self
+                .possible_targets(reservation)
+                .iter()
+                .map(|&cottage| counts[cottage as usize] as u64)
+                .sum();
+            (shared, counts[all] as u64)
+        };
 
-                if count != 0 {
-                    actual_overlaps += 1;
-                    priority[reservation] += count as f64 / reservation_targets.len() as f64;
-                }
+        // Arrivals before each departure, the reservation itself among them
+        let mut by_arrival = staying.clone();
+        by_arrival.sort_by_key(|&x| reservations.arrival()[x]);
+        let mut by_departure = staying;
+        by_departure.sort_by_key(|&x| reservations.departure()[x]);
+
+        let mut arrived = vec![(0u64, 0u64); self.reservations];
+        let mut next = 0;
+        for &reservation in &by_departure {
+            let departure = reservations.departure()[reservation];
+            while next < by_arrival.len() && reservations.arrival()[by_arrival[next]] < departure {
+                count(&mut counts, by_arrival[next]);
+                next += 1;
             }
+            arrived[reservation] = sum(&counts, reservation);
+        }
 
-            if actual_overlaps != 0 {
-                priority[reservation] /= actual_overlaps as f64;
+        // Less the departures up to each arrival
+        counts.fill(0);
+        let mut priority = vec![0f64; self.reservations];
+        let mut next = 0;
+        for &reservation in &by_arrival {
+            let arrival = reservations.arrival()[reservation];
+            while next < by_departure.len()
+                && reservations.departure()[by_departure[next]] <= arrival
+            {
+                count(&mut counts, by_departure[next]);
+                next += 1;
+            }
+            let (departed_shared, departed) = sum(&counts, reservation);
+            let targets = self.possible_targets(reservation).len() as u64;
+            let shared = arrived[reservation].0 - departed_shared - targets;
+            let others = arrived[reservation].1 - departed - 1;
+            if others != 0 {
+                priority[reservation] = shared as f64 / targets as f64 / others as f64;
             }
         }
         self.priority = priority;
     }
 
-    pub fn calculate(cottages: &Cottages, reservations: &Reservations) -> Self {
-        let mut allocation_penalty =
-            vec![Penalty::Free; cottages.cottages() * reservations.reservations()];
-
-        let mut reallocatable = vec![true; reservations.reservations()];
-
-        let index =
-            |reservation: usize, cottage: usize| reservation * cottages.cottages() + cottage;
-
-        // Reservations with pre-defined cottage can only be allocated to that one
-        for reservation in 0..reservations.reservations() {
-            let forced_cottage = reservations.cottage_number.get(reservation).unwrap();
-            if let Some(cottage) = forced_cottage {
-                let start = index(reservation, 0);
-                let end = index(reservation + 1, 0);
-                let _ = &allocation_penalty[start..end].fill(Penalty::Impossible);
-                allocation_penalty[start + *cottage] = Penalty::Free;
+    // Returns whether the cottage was still a target
+    fn remove_target(targets: &mut Vec<u32>, cottage: u32) -> bool {
+        match targets.binary_search(&cottage) {
+            Ok(i) => {
+                targets.remove(i);
+                true
             }
+            Err(_) => false,
         }
+    }
 
-        // Reservation/cottage can be incompatible due to preferences
-        for reservation in 0..reservations.reservations() {
-            let reservation_preferences = reservations.preference[reservation];
-            for cottage in 0..cottages.cottages() {
-                let cottage_preferences = cottages.preference[cottage];
-                let preferences_met = reservation_preferences
-                    .iter()
-                    .zip(cottage_preferences)
-                    .all(|(rp, cp)| *rp <= cp);
+    // Until nothing changes, a reservation with a single possible cottage
+    // takes it from everyone it overlaps with
+    fn propagate_singles(
+        targets: &mut [Vec<u32>],
+        cottages: &Cottages,
+        reservations: &Reservations,
+    ) {
+        loop {
+            let mut count = 0;
+            for reservation in 0..reservations.reservations() {
+                let &[cottage_number] = &targets[reservation][..] else {
+                    continue;
+                };
 
-                if !preferences_met {
-                    allocation_penalty[index(reservation, cottage)] = Penalty::Impossible;
+                // Stays closer than the turnover of the cottage collide as well
+                let turnover = cottages.turnover[cottage_number as usize];
+                let range = reservations.range(reservation);
+                let overlaps = reservations
+                    .get_overlaps_range(range.start.saturating_sub(turnover)..range.end + turnover)
+                    .filter(|&x| x != reservation);
+
+                for overlap in overlaps {
+                    if Self::remove_target(&mut targets[overlap], cottage_number) {
+                        count += 1;
+                    }
                 }
             }
+            if count == 0 {
+                break;
+            }
         }
+    }
 
-        // Class mismatch
-        for reservation in 0..reservations.reservations() {
-            let reservation_class = reservations.class[reservation];
-            for cottage in 0..cottages.cottages() {
-                let cottage_class = cottages.class[cottage];
-                let ap = match reservation_class.cmp(&cottage_class) {
-                    std::cmp::Ordering::Less => Penalty::Upgrade,
-                    std::cmp::Ordering::Equal => Penalty::Free,
-                    std::cmp::Ordering::Greater => Penalty::Impossible,
-                };
-                if ap > allocation_penalty[index(reservation, cottage)] {
-                    allocation_penalty[index(reservation, cottage)] = ap;
+    // Hall's theorem: if k reservations that all take up a cottage on the same day
+    // can only use the same k cottages between them,
+    // those cottages are full for as long as all k take them up.
+    // Anyone else who would take one of them up during that time can not use it.
+    // Groups are formed per arrival day from a set of targets and everyone
+    // whose targets are a subset of it.
+    // More reservations than cottages can not all be placed: such a crowd is recorded,
+    // and nothing is claimed on its day, the solver decides who gives way.
+    fn propagate_groups(
+        targets: &mut [Vec<u32>],
+        cottages: &Cottages,
+        reservations: &Reservations,
+        crowds: &mut Vec<Crowd>,
+    ) -> usize {
+        // Both sorted
+        let is_subset = |a: &[u32], b: &[u32]| {
+            let mut b = b.iter();
+            a.len() <= b.len() && a.iter().all(|x| b.any(|y| y == x))
+        };
+        let occupied = |reservation: usize, cottage: u32| {
+            Problem::occupied_in(cottages, reservations, reservation, cottage as usize)
+        };
+        // Until when a reservation takes up whichever of its cottages it gets
+        let taken_until = |targets: &[u32], reservation: usize| {
+            targets
+                .iter()
+                .map(|&cottage| occupied(reservation, cottage).end)
+                .min()
+                .unwrap_or(0)
+        };
+        let max_turnover = cottages.turnover.iter().max().copied().unwrap_or(0);
+
+        let mut days = reservations.arrival().to_vec();
+        days.sort_unstable();
+        days.dedup();
+
+        let mut count = 0;
+        for day in days {
+            let present: Vec<usize> = reservations
+                .get_overlaps_range(day.saturating_sub(max_turnover)..day + 1)
+                .filter(|&x| {
+                    !targets[x].is_empty()
+                        && reservations.arrival()[x] <= day
+                        && day < taken_until(&targets[x], x)
+                })
+                .collect();
+
+            // A group needs as many reservations as cottages,
+            // so only those with at most as many targets as there are stays can be in one.
+            // Identical target sets are bucketed, each is checked once.
+            // May be stale within the day, but targets only ever shrink,
+            // so a group found with them is still full
+            let mut sets: Vec<(Vec<u32>, Vec<usize>)> = Vec::new();
+            let mut index: HashMap<Vec<u32>, usize> = HashMap::new();
+            for &reservation in &present {
+                let reservation_targets = &targets[reservation];
+                if reservation_targets.len() > present.len() {
+                    continue;
+                }
+                match index.get(reservation_targets) {
+                    Some(&i) => sets[i].1.push(reservation),
+                    None => {
+                        index.insert(reservation_targets.clone(), sets.len());
+                        sets.push((reservation_targets.clone(), vec![reservation]));
+                    }
                 }
             }
-        }
 
-        // Size mismatch
-        for reservation in 0..reservations.reservations() {
-            let reservation_people = reservations.people[reservation];
-            for cottage in 0..cottages.cottages() {
-                let cottage_people = cottages.capacity[cottage];
-                let ap = match reservation_people.cmp(&cottage_people) {
-                    std::cmp::Ordering::Less => Penalty::Upgrade,
-                    std::cmp::Ordering::Equal => Penalty::Free,
-                    std::cmp::Ordering::Greater => Penalty::Impossible,
-                };
-                if ap > allocation_penalty[index(reservation, cottage)] {
-                    allocation_penalty[index(reservation, cottage)] = ap;
+            // Everyone whose targets are a subset of each set.
+            // Comparing sets is quadratic, past a limit only identical sets group.
+            // Single targets are handled by propagate_singles, including turnover.
+            let groups: Vec<(usize, Vec<usize>)> = (0..sets.len())
+                .filter(|&i| sets[i].0.len() >= 2)
+                .map(|i| {
+                    let members = match sets.len() <= MAX_TARGET_SETS {
+                        true => (0..sets.len())
+                            .filter(|&j| is_subset(&sets[j].0, &sets[i].0))
+                            .flat_map(|j| sets[j].1.iter().copied())
+                            .collect(),
+                        false => sets[i].1.clone(),
+                    };
+                    (i, members)
+                })
+                .collect();
+
+            let mut crowded = false;
+            for (i, members) in &groups {
+                if members.len() > sets[*i].0.len() {
+                    Self::add_crowd(crowds, day, members.clone(), &sets[*i].0);
+                    crowded = true;
                 }
             }
-        }
+            if crowded {
+                continue;
+            }
 
-        // Propagate pre-defined cottage knowledge
-        // If a reservation can be assigned to only a single cottage,
-        // Then overlapping reservations can not
-        // TODO: Do this for 2-pairs
+            for (i, members) in groups {
+                let group_targets = &sets[i].0;
+                // Smaller groups leave room
+                if members.len() != group_targets.len() {
+                    continue;
+                }
 
-        loop {
-            let mut count = 0;
-            'r: for reservation in 0..reservations.reservations() {
-                let penalties =
-                    &allocation_penalty[index(reservation, 0)..index(reservation + 1, 0)];
-
-                let mut possible_count = 0;
-                let mut cottage_number = 0;
-                for (i, penalty) in penalties.iter().enumerate() {
-                    match penalty {
-                        Penalty::Impossible => (),
-                        _ if possible_count > 0 => continue 'r,
-                        _ => {
-                            possible_count += 1;
-                            cottage_number = i
-                        }
+                // All members take up the cottages of the group during this time
+                let start = members
+                    .iter()
+                    .map(|&x| reservations.arrival()[x])
+                    .max()
+                    .unwrap();
+                let end = members
+                    .iter()
+                    .map(|&x| taken_until(group_targets, x))
+                    .min()
+                    .unwrap();
+
+                let mut claims = Vec::new();
+                let mut emptied = Vec::new();
+                for other in
+                    reservations.get_overlaps_range(start.saturating_sub(max_turnover)..end)
+                {
+                    if members.contains(&other) || targets[other].is_empty() {
+                        continue;
+                    }
+                    let claimed: Vec<u32> = targets[other]
+                        .iter()
+                        .copied()
+                        .filter(|&cottage| {
+                            group_targets.binary_search(&cottage).is_ok()
+                                && occupied(other, cottage).start < end
+                                && start < occupied(other, cottage).end
+                        })
+                        .collect();
+                    if claimed.len() == targets[other].len() {
+                        emptied.push(other);
                     }
+                    claims.push((other, claimed));
                 }
-                if possible_count == 1 {
-                    reallocatable[reservation] = false;
-                    let overlaps = reservations.get_overlaps(reservation);
-
-                    for overlap in overlaps {
-                        if allocation_penalty[index(overlap, cottage_number)] != Penalty::Impossible
-                        {
-                            allocation_penalty[index(overlap, cottage_number)] =
-                                Penalty::Impossible;
+
+                // Someone would be left without a cottage, so not everyone can be placed
+                if !emptied.is_empty() {
+                    for other in emptied {
+                        let day = start.max(reservations.arrival()[other]);
+                        let crowd = [&members[..], &[other]].concat();
+                        Self::add_crowd(crowds, day, crowd, group_targets);
+                    }
+                    continue;
+                }
+                for (other, claimed) in claims {
+                    for cottage in claimed {
+                        if Self::remove_target(&mut targets[other], cottage) {
                             count += 1;
                         }
                     }
                 }
             }
-            if count == 0 {
-                break;
-            }
+        }
+        count
+    }
+
+    fn add_crowd(
+        crowds: &mut Vec<Crowd>,
+        day: usize,
+        mut reservations: Vec<usize>,
+        cottages: &[u32],
+    ) {
+        reservations.sort_unstable();
+        let crowd = Crowd {
+            day,
+            reservations,
+            cottages: cottages.iter().map(|&cottage| cottage as usize).collect(),
+        };
+        if !crowds.contains(&crowd) {
+            crowds.push(crowd);
+        }
+    }
+
+    // Unmet `soft` preferences are allowed, they are costed separately
+    pub fn calculate(cottages: &Cottages, reservations: &Reservations, soft: &AmenitySet) -> Self {
+        assert!(
+            u32::try_from(cottages.cottages()).is_ok(),
+            "too many cottages"
+        );
+        let upgrades = Upgrades::new(cottages, reservations);
+
+        // Only possible pairs are kept, so there is no reservations x cottages matrix
+        let mut targets: Vec<Vec<u32>> = (0..reservations.reservations())
+            .map(|reservation| {
+                // Reservations with pre-defined cottage can only be allocated to that one
+                let candidates = match reservations.cottage_number[reservation] {
+                    Some(cottage) => cottage..cottage + 1,
+                    None => 0..cottages.cottages(),
+                };
+                let reservation_preferences = reservations.preference[reservation].difference(soft);
+
+                candidates
+                    .filter(|&cottage| {
+                        // Reservation/cottage can be incompatible due to hard preferences
+                        reservation_preferences.is_subset(&cottages.preference[cottage])
+                    })
+                    .filter(|&cottage| {
+                        // Blocked cottages can not take stays that overlap the block,
+                        // the turnover days after a stay can not be blocked either
+                        let occupied =
+                            Problem::occupied_in(cottages, reservations, reservation, cottage);
+                        !cottages.is_blocked(cottage, occupied)
+                    })
+                    .filter(|&cottage| {
+                        // Too small or of a lower class
+                        upgrades.penalty(cottage, reservation) != Penalty::Impossible
+                    })
+                    .map(|cottage| cottage as u32)
+                    .collect()
+            })
+            .collect();
+
+        // Propagate pre-defined cottage knowledge
+        // If a reservation can be assigned to only a single cottage,
+        // Then overlapping reservations can not
+        Self::propagate_singles(&mut targets, cottages, reservations);
+
+        // Groups claim cottages the same way, until a round claims nothing
+        let mut crowds = Vec::new();
+        while Self::propagate_groups(&mut targets, cottages, reservations, &mut crowds) > 0 {
+            Self::propagate_singles(&mut targets, cottages, reservations);
         }
 
+        // Nothing to choose from with one cottage or less
+        let reallocatable: Vec<bool> = targets.iter().map(|x| x.len() > 1).collect();
+
         let reallocatable_reservations: Vec<_> = reallocatable
             .iter()
             .enumerate()
@@ -214,18 +519,41 @@ impl AllocationPenalty {
             .map(|x| x.0)
             .collect();
 
+        // Each list is freed once copied, so the pairs are not held twice
+        let pairs = targets.iter().map(Vec::len).sum();
+        let mut offsets = Vec::with_capacity(targets.len() + 1);
+        let mut flat_targets = Vec::with_capacity(pairs);
+        let mut upgrade_costs = Vec::with_capacity(pairs);
+        offsets.push(0);
+        for (reservation, reservation_targets) in targets.into_iter().enumerate() {
+            // Stored exactly like the targets, a cost is never capped
+            upgrade_costs.extend(reservation_targets.iter().map(|&cottage| {
+                let cost = upgrades
+                    .penalty(cottage as usize, reservation)
+                    .upgrade_cost();
+                assert!(
+                    cost <= u32::MAX as usize,
+                    "upgrade cost {cost} of reservation {reservation} does not fit in 32 bits"
+                );
+                cost as u32
+            }));
+            flat_targets.extend(reservation_targets);
+            offsets.push(flat_targets.len());
+        }
+
         let mut ap = Self {
-            allocation_penalty,
+            offsets,
+            targets: flat_targets,
+            upgrade_costs,
             cottages: cottages.cottages(),
             reservations: reservations.reservations(),
             reallocatable,
             reallocatable_reservations,
-            possible_targets: Vec::new(),
             priority: Vec::new(),
+            crowds,
         };
 
-        ap.calculate_possible_targets();
-        ap.calculate_priority(&reservations);
+        ap.calculate_priority(reservations);
 
         ap
     }
@@ -241,7 +569,8 @@ mod test {
         let mut reservations = Reservations::empty(3);
         reservations.cottage_number = vec![Some(0), None, Some(2)];
 
-        let allocation_penalty = AllocationPenalty::calculate(&cottages, &reservations);
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
 
         assert_eq!(
             allocation_penalty.get_penalty_arr(0),
@@ -283,7 +612,8 @@ mod test {
         let mut reservations = Reservations::empty(2);
         reservations.cottage_number = vec![Some(0), None];
 
-        let allocation_penalty = AllocationPenalty::calculate(&cottages, &reservations);
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
 
         assert_eq!(
             allocation_penalty.get_penalty_arr(0),
@@ -302,16 +632,16 @@ mod test {
 
     #[test]
     fn test_alloc_penalty_preferences() {
-        let mut preferences = [false; 10];
-        preferences[5] = true;
+        let preferences: AmenitySet = [5].into_iter().collect();
 
         let mut cottages = Cottages::empty(3);
-        cottages.preference = vec![[false; 10], preferences.clone(), [true; 10]];
+        cottages.preference = vec![AmenitySet::new(10), preferences.clone(), (0..10).collect()];
 
         let mut reservations = Reservations::empty(1);
         reservations.preference = vec![preferences];
 
-        let allocation_penalty = AllocationPenalty::calculate(&cottages, &reservations);
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
 
         assert_eq!(
             allocation_penalty.get_penalty_arr(0),
@@ -322,6 +652,71 @@ mod test {
         assert_eq!(allocation_penalty.reallocatable_reservations, vec![0]);
     }
 
+    #[test]
+    fn test_alloc_penalty_soft_preferences() {
+        let mut cottages = Cottages::empty(3);
+        cottages.preference = vec![
+            AmenitySet::default(),
+            [5].into_iter().collect(),
+            [2].into_iter().collect(),
+        ];
+
+        let mut reservations = Reservations::empty(1);
+        reservations.preference = vec![[2, 5].into_iter().collect()];
+
+        // Amenity 5 is a wish, amenity 2 is required
+        let soft: AmenitySet = [5].into_iter().collect();
+        let allocation_penalty = AllocationPenalty::calculate(&cottages, &reservations, &soft);
+
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(0),
+            vec![Penalty::Impossible, Penalty::Impossible, Penalty::Free]
+        );
+    }
+
+    #[test]
+    fn test_alloc_penalty_blocks() {
+        let mut cottages = Cottages::empty(3);
+        cottages.blocks[0].push(0..2);
+        cottages.blocks[1].push(4..6);
+
+        let mut reservations = Reservations::empty(1);
+        reservations.set_arrival(vec![1]);
+        reservations.set_stay(vec![3]);
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(0),
+            vec![Penalty::Impossible, Penalty::Free, Penalty::Free]
+        );
+    }
+
+    #[test]
+    fn test_alloc_penalty_turnover() {
+        let mut cottages = Cottages::empty(2);
+        cottages.turnover = vec![1, 0];
+
+        let mut reservations = Reservations::empty(3);
+        reservations.set_arrival(vec![0, 2, 3]);
+        reservations.set_stay(vec![2, 1, 1]);
+        reservations.cottage_number = vec![Some(0), None, None];
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        // Reservation 1 arrives on the turnover day of reservation 0
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(1),
+            vec![Penalty::Impossible, Penalty::Free]
+        );
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(2),
+            vec![Penalty::Free, Penalty::Free]
+        );
+    }
+
     #[test]
     fn test_alloc_penalty_sizes() {
         let mut cottages = Cottages::empty(3);
@@ -330,17 +725,42 @@ mod test {
         let mut reservations = Reservations::empty(1);
         reservations.people = vec![6];
 
-        let allocation_penalty = AllocationPenalty::calculate(&cottages, &reservations);
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
 
         assert_eq!(
             allocation_penalty.get_penalty_arr(0),
-            vec![Penalty::Impossible, Penalty::Free, Penalty::Upgrade]
+            vec![Penalty::Impossible, Penalty::Free, Penalty::Upgrade(1)]
         );
 
         assert_eq!(allocation_penalty.reallocatable, vec![true]);
         assert_eq!(allocation_penalty.reallocatable_reservations, vec![0]);
     }
 
+    #[test]
+    fn test_alloc_penalty_graded() {
+        let mut cottages = Cottages::empty(4);
+        cottages.capacity = vec![2, 4, 12, 12];
+        cottages.class = vec![1, 1, 1, 4];
+
+        let mut reservations = Reservations::empty(1);
+        reservations.people = vec![2];
+        reservations.class = vec![1];
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(0),
+            vec![
+                Penalty::Free,
+                Penalty::Upgrade(1),
+                Penalty::Upgrade(2),
+                Penalty::Upgrade(5),
+            ]
+        );
+    }
+
     #[test]
     fn test_alloc_penalty_class() {
         let mut cottages = Cottages::empty(3);
@@ -349,14 +769,241 @@ mod test {
         let mut reservations = Reservations::empty(1);
         reservations.class = vec![2];
 
-        let allocation_penalty = AllocationPenalty::calculate(&cottages, &reservations);
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
 
         assert_eq!(
             allocation_penalty.get_penalty_arr(0),
-            vec![Penalty::Impossible, Penalty::Free, Penalty::Upgrade]
+            vec![Penalty::Impossible, Penalty::Free, Penalty::Upgrade(1)]
         );
 
         assert_eq!(allocation_penalty.reallocatable, vec![true]);
         assert_eq!(allocation_penalty.reallocatable_reservations, vec![0]);
     }
+
+    #[test]
+    fn test_alloc_penalty_large_upgrade() {
+        let mut cottages = Cottages::empty(3);
+        cottages.class = vec![1, 1000, u32::MAX as usize];
+
+        let reservations = Reservations::empty(1);
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(0),
+            vec![
+                Penalty::Upgrade(1),
+                Penalty::Upgrade(1000),
+                Penalty::Upgrade(u32::MAX as usize)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_alloc_penalty_priority() {
+        // Cottage 2 is the only one for reservation 2, so 0 and 1 share 0 and 1.
+        // Reservation 3 stays later.
+        let mut cottages = Cottages::empty(3);
+        cottages.class = vec![1, 1, 2];
+        let mut reservations = Reservations::empty(4);
+        reservations.class = vec![1, 1, 2, 1];
+        reservations.set_arrival(vec![0, 1, 1, 5]);
+        reservations.set_stay(vec![2, 3, 2, 1]);
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        assert_eq!(allocation_penalty.possible_targets(0), &[0, 1]);
+        assert_eq!(allocation_penalty.priority, vec![0.5, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_alloc_penalty_priority_overlaps() {
+        let mut cottages = Cottages::empty(5);
+        cottages.class = vec![1, 2, 1, 3, 2];
+        let mut reservations = Reservations::empty(8);
+        reservations.class = vec![1, 2, 3, 1, 2, 1, 3, 2];
+        reservations.set_arrival(vec![0, 1, 1, 2, 4, 4, 6, 0]);
+        reservations.set_stay(vec![3, 4, 2, 5, 1, 3, 2, 8]);
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        // Shared targets over every overlap with any target, as a fraction of the targets
+        for reservation in 0..8 {
+            let targets = allocation_penalty.possible_targets(reservation);
+            let others: Vec<_> = reservations
+                .get_overlaps(reservation)
+                .filter(|&x| !allocation_penalty.possible_targets(x).is_empty())
+                .collect();
+            let shared = others
+                .iter()
+                .flat_map(|&x| allocation_penalty.possible_targets(x))
+                .filter(|x| targets.contains(x))
+                .count();
+            let expected = match others.len() {
+                0 => 0f64,
+                n => shared as f64 / targets.len() as f64 / n as f64,
+            };
+            assert_eq!(allocation_penalty.priority[reservation], expected);
+        }
+    }
+
+    #[test]
+    fn test_alloc_penalty_groups() {
+        let mut cottages = Cottages::empty(4);
+        cottages.class = vec![1, 1, 0, 0];
+
+        let mut reservations = Reservations::empty(4);
+        reservations.set_arrival(vec![0, 1, 1, 5]);
+        reservations.set_stay(vec![3, 3, 2, 1]);
+        reservations.class = vec![1, 1, 0, 0];
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        // Reservations 0 and 1 fill cottages 0 and 1 on days 1 and 2
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(2),
+            vec![
+                Penalty::Impossible,
+                Penalty::Impossible,
+                Penalty::Free,
+                Penalty::Free,
+            ]
+        );
+        // Reservation 3 comes later and keeps its upgrades
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(3),
+            vec![
+                Penalty::Upgrade(1),
+                Penalty::Upgrade(1),
+                Penalty::Free,
+                Penalty::Free,
+            ]
+        );
+        assert_eq!(allocation_penalty.reallocatable, vec![true; 4]);
+    }
+
+    #[test]
+    fn test_alloc_penalty_groups_chain() {
+        let mut cottages = Cottages::empty(4);
+        cottages.class = vec![2, 2, 1, 0];
+
+        let mut reservations = Reservations::empty(4);
+        reservations.class = vec![2, 2, 1, 0];
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        // 0 and 1 take cottages 0 and 1, which leaves cottage 2 for 2
+        // and cottage 3 for 3
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(2),
+            vec![
+                Penalty::Impossible,
+                Penalty::Impossible,
+                Penalty::Free,
+                Penalty::Impossible,
+            ]
+        );
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(3),
+            vec![
+                Penalty::Impossible,
+                Penalty::Impossible,
+                Penalty::Impossible,
+                Penalty::Free,
+            ]
+        );
+        assert_eq!(
+            allocation_penalty.reallocatable,
+            vec![true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn test_alloc_penalty_groups_overbooked() {
+        let mut cottages = Cottages::empty(3);
+        cottages.class = vec![2, 2, 1];
+
+        let mut reservations = Reservations::empty(4);
+        reservations.class = vec![2, 2, 1, 0];
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        // Four reservations for three cottages, so one of them has to give way
+        // and nothing is claimed
+        assert_eq!(
+            allocation_penalty.get_penalty_arr(3),
+            vec![
+                Penalty::Upgrade(2),
+                Penalty::Upgrade(2),
+                Penalty::Upgrade(1)
+            ]
+        );
+        assert_eq!(allocation_penalty.reallocatable, vec![true; 4]);
+        assert_eq!(
+            allocation_penalty.crowds,
+            vec![Crowd {
+                day: 0,
+                reservations: vec![0, 1, 2, 3],
+                cottages: vec![0, 1, 2]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_alloc_penalty_groups_turnover() {
+        let mut cottages = Cottages::empty(4);
+        cottages.class = vec![1, 1, 0, 0];
+        cottages.turnover = vec![1, 1, 0, 0];
+
+        // 0 and 1 keep cottages 0 and 1 for their turnover on day 3,
+        // when reservation 2 arrives
+        let mut reservations = Reservations::empty(3);
+        reservations.set_arrival(vec![0, 1, 3]);
+        reservations.set_stay(vec![3, 2, 1]);
+        reservations.class = vec![1, 1, 0];
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+        assert_eq!(allocation_penalty.possible_targets(2), &[2, 3]);
+        assert!(allocation_penalty.crowds.is_empty());
+
+        // Without turnover cottages 0 and 1 are free again on day 3
+        cottages.turnover = vec![0; 4];
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+        assert_eq!(allocation_penalty.possible_targets(2), &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_alloc_penalty_sparse() {
+        let mut cottages = Cottages::empty(4);
+        cottages.class = vec![1, 2, 0, 3];
+        cottages.capacity = vec![4, 4, 4, 6];
+
+        let mut reservations = Reservations::empty(3);
+        reservations.set_arrival(vec![0, 4, 8]);
+        reservations.class = vec![1, 0, 3];
+        reservations.people = vec![4, 2, 8];
+
+        let allocation_penalty =
+            AllocationPenalty::calculate(&cottages, &reservations, &AmenitySet::default());
+
+        // Only possible pairs are stored, the rest reads as impossible
+        assert_eq!(allocation_penalty.possible_targets(0), &[0, 1, 3]);
+        assert_eq!(allocation_penalty.possible_targets(1), &[0, 1, 2, 3]);
+        assert!(allocation_penalty.possible_targets(2).is_empty());
+        assert_eq!(allocation_penalty.possible_pairs(), 7);
+
+        assert_eq!(allocation_penalty.get(2, 0), Penalty::Impossible);
+        assert_eq!(allocation_penalty.get(3, 0), Penalty::Upgrade(3));
+        assert_eq!(allocation_penalty.get(1, 1), Penalty::Upgrade(3));
+        assert_eq!(allocation_penalty.get(3, 2), Penalty::Impossible);
+    }
 }
@@ -0,0 +1,246 @@
+use std::hash::{Hash, Hasher};
+
+// Names of the amenities a park offers, the position of a name is its bit in an `AmenitySet`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AmenitySchema {
+    names: Vec<String>,
+    // Amenities that are wishes rather than requirements.
+    // Every other amenity is hard, a cottage without it can not take the reservation.
+    soft: AmenitySet,
+}
+
+impl AmenitySchema {
+    pub fn new(names: Vec<String>) -> Self {
+        Self {
+            names,
+            soft: AmenitySet::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|x| x == name)
+    }
+
+    pub fn name(&self, amenity: usize) -> &str {
+        &self.names[amenity]
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names[..]
+    }
+
+    pub fn set_soft(&mut self, amenity: usize) {
+        self.soft.insert(amenity);
+    }
+
+    pub fn is_soft(&self, amenity: usize) -> bool {
+        self.soft.contains(amenity)
+    }
+
+    pub fn soft(&self) -> &AmenitySet {
+        &self.soft
+    }
+}
+
+// Bitset of amenities, as wide as the schema it was built for
+#[derive(Debug, Clone, Default)]
+pub struct AmenitySet {
+    words: Vec<u64>,
+}
+
+impl AmenitySet {
+    // The words up to the last one with a bit set, sets of different widths
+    // that hold the same amenities have the same significant words
+    fn significant(&self) -> &[u64] {
+        let len = self
+            .words
+            .iter()
+            .rposition(|&x| x != 0)
+            .map_or(0, |x| x + 1);
+        &self.words[..len]
+    }
+
+    pub fn new(width: usize) -> Self {
+        Self {
+            words: vec![0; width.div_ceil(64)],
+        }
+    }
+
+    pub fn insert(&mut self, amenity: usize) {
+        let word = amenity / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (amenity % 64);
+    }
+
+    pub fn contains(&self, amenity: usize) -> bool {
+        self.words
+            .get(amenity / 64)
+            .is_some_and(|word| word & (1 << (amenity % 64)) != 0)
+    }
+
+    // Missing words count as empty, so sets of different widths compare
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.words
+            .iter()
+            .enumerate()
+            .all(|(i, word)| word & !other.words.get(i).copied().unwrap_or(0) == 0)
+    }
+
+    // Number of amenities in `self` that are in `mask` but not in `other`
+    pub fn count_missing(&self, other: &Self, mask: &Self) -> usize {
+        self.words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                let other = other.words.get(i).copied().unwrap_or(0);
+                let mask = mask.words.get(i).copied().unwrap_or(0);
+                (word & mask & !other).count_ones() as usize
+            })
+            .sum()
+    }
+
+    // Amenities in `self` that are not in `other`
+    pub fn difference(&self, other: &Self) -> Self {
+        let words = self
+            .words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| word & !other.words.get(i).copied().unwrap_or(0))
+            .collect();
+        Self { words }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|x| x.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&x| x == 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+impl PartialEq for AmenitySet {
+    fn eq(&self, other: &Self) -> bool {
+        self.significant() == other.significant()
+    }
+}
+
+impl Eq for AmenitySet {}
+
+impl Hash for AmenitySet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.significant().hash(state);
+    }
+}
+
+impl FromIterator<usize> for AmenitySet {
+    fn from_iter<T: IntoIterator<Item = usize>>(iter: T) -> Self {
+        let mut set = Self::default();
+        for amenity in iter {
+            set.insert(amenity);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_amenity_set() {
+        let mut set = AmenitySet::new(70);
+        set.insert(3);
+        set.insert(68);
+
+        assert!(set.contains(3));
+        assert!(set.contains(68));
+        assert!(!set.contains(4));
+        assert!(!set.contains(200));
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![3, 68]);
+    }
+
+    #[test]
+    fn test_amenity_subset() {
+        let small: AmenitySet = [1, 65].into_iter().collect();
+        let large: AmenitySet = [0, 1, 65, 66].into_iter().collect();
+
+        assert!(small.is_subset(&large));
+        assert!(!large.is_subset(&small));
+        assert!(AmenitySet::new(10).is_subset(&small));
+        assert!(AmenitySet::default().is_subset(&AmenitySet::new(128)));
+
+        let narrow: AmenitySet = [1].into_iter().collect();
+        assert!(narrow.is_subset(&small));
+        assert!(!small.is_subset(&narrow));
+    }
+
+    #[test]
+    fn test_amenity_set_width() {
+        let narrow: AmenitySet = [3].into_iter().collect();
+        let mut wide = AmenitySet::new(200);
+        wide.insert(3);
+
+        assert_eq!(narrow, wide);
+        assert_eq!(AmenitySet::default(), AmenitySet::new(128));
+        assert_ne!(narrow, AmenitySet::new(200));
+
+        let hash = |set: &AmenitySet| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            set.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&narrow), hash(&wide));
+    }
+
+    #[test]
+    fn test_amenity_missing() {
+        let wanted: AmenitySet = [1, 2, 65].into_iter().collect();
+        let offered: AmenitySet = [2].into_iter().collect();
+        let soft: AmenitySet = [1, 2, 65, 100].into_iter().collect();
+
+        assert_eq!(wanted.count_missing(&offered, &soft), 2);
+        assert_eq!(wanted.count_missing(&offered, &AmenitySet::default()), 0);
+        assert_eq!(
+            wanted.difference(&soft).iter().collect::<Vec<_>>(),
+            Vec::<usize>::new()
+        );
+        assert_eq!(
+            wanted.difference(&offered).iter().collect::<Vec<_>>(),
+            vec![1, 65]
+        );
+    }
+
+    #[test]
+    fn test_amenity_schema() {
+        let schema = AmenitySchema::new(vec!["Sauna".to_string(), "EV charger".to_string()]);
+        assert_eq!(schema.len(), 2);
+        assert_eq!(schema.index("EV charger"), Some(1));
+        assert_eq!(schema.index("Pets allowed"), None);
+        assert_eq!(schema.name(0), "Sauna");
+
+        let mut schema = schema;
+        schema.set_soft(1);
+        assert!(schema.is_soft(1));
+        assert!(!schema.is_soft(0));
+    }
+}
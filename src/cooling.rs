@@ -0,0 +1,340 @@
+use std::{fmt::Debug, str::FromStr};
+
+use crate::instance::Instance;
+
+// Multiplicative schedules can never reach zero
+pub const MIN_TEMPERATURE: f64 = 1e-6;
+
+// How far the annealing run has come
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub iteration: u64,
+    pub iterations: u64,
+    // Fraction of the wall-clock budget that has been used
+    pub elapsed: f64,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        (self.iteration as f64 / self.iterations as f64).min(1f64)
+    }
+}
+
+pub trait CoolingSchedule: Debug + Send {
+    fn temperature(&self, progress: &Progress) -> f64;
+}
+
+// T = T0 + (T1 - T0) * k / n
+#[derive(Debug, Clone)]
+pub struct Linear {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl CoolingSchedule for Linear {
+    fn temperature(&self, progress: &Progress) -> f64 {
+        self.start + (self.end - self.start) * progress.fraction()
+    }
+}
+
+// T = T0 * (T1 / T0) ^ (k / n)
+#[derive(Debug, Clone)]
+pub struct Geometric {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl CoolingSchedule for Geometric {
+    fn temperature(&self, progress: &Progress) -> f64 {
+        self.start * (self.end / self.start).powf(progress.fraction())
+    }
+}
+
+// T = T0 / (1 + a * ln(1 + k)), with a such that T(n) = T1
+#[derive(Debug, Clone)]
+pub struct Logarithmic {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl CoolingSchedule for Logarithmic {
+    fn temperature(&self, progress: &Progress) -> f64 {
+        let a = (self.start / self.end - 1f64) / (1f64 + progress.iterations as f64).ln();
+        self.start / (1f64 + a * (1f64 + progress.iteration as f64).ln())
+    }
+}
+
+// T(k + 1) = T(k) / (1 + b * T(k)), with b such that T(n) = T1
+#[derive(Debug, Clone)]
+pub struct LundyMees {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl CoolingSchedule for LundyMees {
+    fn temperature(&self, progress: &Progress) -> f64 {
+        self.start / (1f64 + progress.fraction() * (self.start / self.end - 1f64))
+    }
+}
+
+// Geometric over the elapsed fraction of the wall-clock budget,
+// so a run ends cold no matter how many iterations fit in the budget
+#[derive(Debug, Clone)]
+pub struct TimeBased {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl CoolingSchedule for TimeBased {
+    fn temperature(&self, progress: &Progress) -> f64 {
+        self.start * (self.end / self.start).powf(progress.elapsed.clamp(0f64, 1f64))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Linear,
+    Geometric,
+    Logarithmic,
+    LundyMees,
+    TimeBased,
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "geometric" => Ok(Self::Geometric),
+            "logarithmic" => Ok(Self::Logarithmic),
+            "lundy-mees" => Ok(Self::LundyMees),
+            "time" => Ok(Self::TimeBased),
+            _ => Err(format!(
+                "unknown schedule `{s}`, expected one of: linear, geometric, logarithmic, lundy-mees, time"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CoolingConfig {
+    pub schedule: Schedule,
+    // None: calibrate from sampled neighbor deltas
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    // Number of neighbor moves sampled for calibration
+    pub samples: usize,
+    // Share of sampled uphill moves the calibrated start temperature accepts
+    pub acceptance: f64,
+}
+
+impl Default for CoolingConfig {
+    fn default() -> Self {
+        Self {
+            schedule: Schedule::Linear,
+            start: None,
+            end: Some(0f64),
+            samples: 2000,
+            acceptance: 0.5,
+        }
+    }
+}
+
+impl CoolingConfig {
+    pub fn build(&self, start: f64, end: f64) -> Box<dyn CoolingSchedule> {
+        // Only the linear schedule can reach zero
+        let end = match self.schedule {
+            Schedule::Linear => end.max(0f64),
+            _ => end.max(MIN_TEMPERATURE),
+        };
+        let start = start.max(end);
+
+        match self.schedule {
+            Schedule::Linear => Box::new(Linear { start, end }),
+            Schedule::Geometric => Box::new(Geometric { start, end }),
+            Schedule::Logarithmic => Box::new(Logarithmic { start, end }),
+            Schedule::LundyMees => Box::new(LundyMees { start, end }),
+            Schedule::TimeBased => Box::new(TimeBased { start, end }),
+        }
+    }
+
+    // Fill in missing temperatures from sampled objective deltas.
+    // The start is chosen such that the sampled uphill moves would be
+    // accepted at the target `acceptance` ratio, the end such that the
+    // smallest uphill move is accepted once in a hundred.
+    pub fn calibrate(&self, instance: &mut Instance) -> (f64, f64) {
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            return (start, end);
+        }
+
+        // Downhill moves are always accepted, so only uphill moves
+        // say anything about the temperature
+        let uphill: Vec<_> = sample_deltas(instance, self.samples)
+            .into_iter()
+            .filter(|&delta| delta > 0)
+            .collect();
+
+        let start = self
+            .start
+            .unwrap_or_else(|| initial_temperature(&uphill, self.acceptance));
+        let end = self.end.unwrap_or_else(|| {
+            uphill
+                .iter()
+                .min()
+                .map(|&min| -min as f64 / 0.01f64.ln())
+                .unwrap_or(MIN_TEMPERATURE)
+        });
+        (start, end)
+    }
+}
+
+// Share of moves the annealing loop would accept at temperature `temp`
+pub fn acceptance_ratio(deltas: &[isize], temp: f64) -> f64 {
+    let accepted: f64 = deltas
+        .iter()
+        .map(|&delta| match delta {
+            delta if delta < 0 => 1f64,
+            delta => (-delta as f64 / temp).exp(),
+        })
+        .sum();
+    accepted / deltas.len() as f64
+}
+
+// Bisect (in log space) for the temperature at which
+// the sampled moves are accepted at the target ratio
+pub fn initial_temperature(deltas: &[isize], acceptance: f64) -> f64 {
+    if deltas.is_empty() || acceptance_ratio(deltas, MIN_TEMPERATURE) >= acceptance {
+        return MIN_TEMPERATURE;
+    }
+
+    let max = deltas.iter().max().copied().unwrap_or(1).max(1) as f64;
+    let mut low = MIN_TEMPERATURE.ln();
+    let mut high = (max * 1e6).ln();
+    if acceptance_ratio(deltas, high.exp()) < acceptance {
+        return high.exp();
+    }
+
+    for _ in 0..64 {
+        let mid = 0.5 * (low + high);
+        if acceptance_ratio(deltas, mid.exp()) < acceptance {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    high.exp()
+}
+
+// Objective deltas of random neighbor moves, every move is rolled back
+pub fn sample_deltas(instance: &mut Instance, samples: usize) -> Vec<isize> {
+    (0..samples)
+        .map(|_| {
+            instance.neighbor();
+            let delta = instance.chain.cost().objective(&instance.problem.weights);
+            instance.reject_chain();
+            delta
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        cost::Cost,
+        problem::{Cottages, Problem, Reservations},
+        solution::Solution,
+    };
+
+    use super::*;
+
+    fn progress(iteration: u64) -> Progress {
+        Progress {
+            iteration,
+            iterations: 100,
+            elapsed: iteration as f64 / 100f64,
+        }
+    }
+
+    #[test]
+    fn test_schedule_endpoints() {
+        for schedule in [
+            Schedule::Linear,
+            Schedule::Geometric,
+            Schedule::Logarithmic,
+            Schedule::LundyMees,
+            Schedule::TimeBased,
+        ] {
+            let config = CoolingConfig {
+                schedule,
+                ..Default::default()
+            };
+            let cooling = config.build(2f64, 0.5);
+
+            assert!((cooling.temperature(&progress(0)) - 2f64).abs() < 1e-9);
+            assert!((cooling.temperature(&progress(100)) - 0.5).abs() < 1e-9);
+
+            let temperatures: Vec<_> = (0..=100)
+                .map(|i| cooling.temperature(&progress(i)))
+                .collect();
+            assert!(temperatures.windows(2).all(|x| x[0] >= x[1]));
+        }
+    }
+
+    #[test]
+    fn test_linear() {
+        let config = CoolingConfig::default();
+        let cooling = config.build(0.2, config.end.unwrap());
+        assert!((cooling.temperature(&progress(50)) - 0.1).abs() < 1e-9);
+        assert_eq!(cooling.temperature(&progress(100)), 0f64);
+    }
+
+    #[test]
+    fn test_initial_temperature() {
+        let deltas = vec![-3, -1, 0, 1, 2, 4, 6, 12, 24, 48];
+        for acceptance in [0.35, 0.5, 0.8, 0.95] {
+            let temp = initial_temperature(&deltas, acceptance);
+            assert!((acceptance_ratio(&deltas, temp) - acceptance).abs() < 1e-6);
+        }
+
+        // Downhill moves are always accepted
+        assert_eq!(initial_temperature(&deltas, 0.2), MIN_TEMPERATURE);
+        assert_eq!(initial_temperature(&[], 0.5), MIN_TEMPERATURE);
+    }
+
+    #[test]
+    fn test_calibrate_rolls_back() {
+        let mut cottages = Cottages::empty(6);
+        let mut reservations = Reservations::empty(8);
+        cottages.class = vec![0, 0, 1, 1, 2, 2];
+        reservations.set_arrival(vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        reservations.set_stay(vec![3, 2, 4, 1, 3, 2, 1, 2]);
+        let problem = Problem::new(cottages, reservations, 0);
+        let solution = Solution::empty(&problem);
+        let mut instance = Instance::with_seed(problem, solution, 1);
+        instance.repair().unwrap();
+        instance.accept_chain();
+
+        let mapping = instance.solution.mapping().to_vec();
+        let cost = Cost::calculate_instance(&instance);
+
+        let config = CoolingConfig {
+            samples: 500,
+            ..Default::default()
+        };
+        let (start, end) = config.calibrate(&mut instance);
+        assert!(start > 0f64);
+        assert_eq!(end, 0f64);
+
+        assert_eq!(instance.solution.mapping(), &mapping[..]);
+        assert_eq!(Cost::calculate_instance(&instance), cost);
+        assert!(instance.chain.is_empty());
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        assert_eq!("lundy-mees".parse(), Ok(Schedule::LundyMees));
+        assert_eq!("time".parse(), Ok(Schedule::TimeBased));
+        assert!("fast".parse::<Schedule>().is_err());
+    }
+}
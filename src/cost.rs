@@ -1,29 +1,65 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{
     gap_cost::{calculate_cottage_gaps2, calculate_gaps},
     instance::Instance,
+    preference_cost::{calculate_preference, calculate_preferences},
     problem::Problem,
     solution::Solution,
     upgrade_cost::{calculate_upgrade, calculate_upgrades},
 };
 
+// Business weight of each cost component in the objective
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostWeights {
+    pub gaps: isize,
+    pub gaps_fri_thu: isize,
+    pub gaps_legionella: isize,
+    pub upgrades: isize,
+    // Per unmet soft preference
+    pub preferences: isize,
+    // Per reservation left without a cottage, only when that is allowed
+    pub unallocated: isize,
+}
+
+impl Default for CostWeights {
+    fn default() -> Self {
+        Self {
+            gaps: 6,
+            gaps_fri_thu: -3,
+            gaps_legionella: 12,
+            upgrades: 1,
+            preferences: 1,
+            unallocated: 1000,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Cost {
     gaps: isize,
     gaps_fri_thu: isize,
     gaps_legionella: isize,
     upgrades: isize,
+    preferences: isize,
+    unallocated: isize,
 }
 
 impl Cost {
     pub fn calculate(problem: &Problem, solution: &Solution) -> Self {
         let (gaps, gaps_fri_thu, gaps_legionella) = calculate_gaps(problem, solution);
         let upgrades = calculate_upgrades(problem, solution);
+        let preferences = calculate_preferences(problem, solution);
+        let unallocated = solution.unallocated().len();
 
         Self {
             gaps: gaps as isize,
             gaps_fri_thu: gaps_fri_thu as isize,
             gaps_legionella: gaps_legionella as isize,
             upgrades: upgrades as isize,
+            preferences: preferences as isize,
+            unallocated: unallocated as isize,
         }
     }
 
@@ -41,25 +77,67 @@ impl Cost {
             calculate_cottage_gaps2(problem, solution, cottage);
 
         let upgrades = calculate_upgrade(problem, solution, reservation);
+        let preferences = calculate_preference(problem, solution, reservation);
+        let unallocated = solution.mapping_single(reservation).is_none() as usize;
 
         Self {
             gaps: gaps as isize,
             gaps_fri_thu: gaps_fri_thu as isize,
             gaps_legionella: gaps_legionella as isize,
             upgrades: upgrades as isize,
+            preferences: preferences as isize,
+            unallocated: unallocated as isize,
         }
     }
 
-    pub fn objective(&self) -> isize {
-        return 6 * self.gaps - 3 * self.gaps_fri_thu + 12 * self.gaps_legionella + self.upgrades;
+    pub fn objective(&self, weights: &CostWeights) -> isize {
+        weights.gaps * self.gaps
+            + weights.gaps_fri_thu * self.gaps_fri_thu
+            + weights.gaps_legionella * self.gaps_legionella
+            + weights.upgrades * self.upgrades
+            + weights.preferences * self.preferences
+            + weights.unallocated * self.unallocated
+    }
+
+    pub fn gaps(&self) -> isize {
+        self.gaps
+    }
+
+    pub fn gaps_fri_thu(&self) -> isize {
+        self.gaps_fri_thu
+    }
+
+    pub fn gaps_legionella(&self) -> isize {
+        self.gaps_legionella
+    }
+
+    pub fn upgrades(&self) -> isize {
+        self.upgrades
+    }
+
+    pub fn preferences(&self) -> isize {
+        self.preferences
+    }
+
+    pub fn unallocated(&self) -> isize {
+        self.unallocated
     }
 
-    pub fn new(gaps: isize, gaps_fri_thu: isize, gaps_legionella: isize, upgrades: isize) -> Self {
+    pub fn new(
+        gaps: isize,
+        gaps_fri_thu: isize,
+        gaps_legionella: isize,
+        upgrades: isize,
+        preferences: isize,
+        unallocated: isize,
+    ) -> Self {
         Self {
             gaps,
             gaps_fri_thu,
             gaps_legionella,
             upgrades,
+            preferences,
+            unallocated,
         }
     }
 
@@ -69,6 +147,8 @@ impl Cost {
             gaps_fri_thu: 0,
             gaps_legionella: 0,
             upgrades: 0,
+            preferences: 0,
+            unallocated: 0,
         }
     }
 }
@@ -82,6 +162,8 @@ impl std::ops::Add for Cost {
             gaps_fri_thu: self.gaps_fri_thu + other.gaps_fri_thu,
             gaps_legionella: self.gaps_legionella + other.gaps_legionella,
             upgrades: self.upgrades + other.upgrades,
+            preferences: self.preferences + other.preferences,
+            unallocated: self.unallocated + other.unallocated,
         }
     }
 }
@@ -92,6 +174,8 @@ impl std::ops::AddAssign for Cost {
         self.gaps_fri_thu += rhs.gaps_fri_thu;
         self.gaps_legionella += rhs.gaps_legionella;
         self.upgrades += rhs.upgrades;
+        self.preferences += rhs.preferences;
+        self.unallocated += rhs.unallocated;
     }
 }
 
@@ -104,6 +188,8 @@ impl std::ops::Sub for Cost {
             gaps_fri_thu: self.gaps_fri_thu - rhs.gaps_fri_thu,
             gaps_legionella: self.gaps_legionella - rhs.gaps_legionella,
             upgrades: self.upgrades - rhs.upgrades,
+            preferences: self.preferences - rhs.preferences,
+            unallocated: self.unallocated - rhs.unallocated,
         }
     }
 }
@@ -114,12 +200,31 @@ impl std::ops::SubAssign for Cost {
         self.gaps_fri_thu -= rhs.gaps_fri_thu;
         self.gaps_legionella -= rhs.gaps_legionella;
         self.upgrades -= rhs.upgrades;
+        self.preferences -= rhs.preferences;
+        self.unallocated -= rhs.unallocated;
     }
 }
 
 #[cfg(test)]
 mod test {
+    use super::*;
 
     #[test]
     fn test_calculate_cost() {}
+
+    #[test]
+    fn test_objective_weights() {
+        let cost = Cost::new(2, 1, 1, 3, 2, 0);
+        assert_eq!(cost.objective(&CostWeights::default()), 12 - 3 + 12 + 3 + 2);
+
+        let weights = CostWeights {
+            gaps: 1,
+            gaps_fri_thu: 0,
+            gaps_legionella: 100,
+            upgrades: 10,
+            preferences: 0,
+            unallocated: 1000,
+        };
+        assert_eq!(cost.objective(&weights), 2 + 100 + 30);
+    }
 }
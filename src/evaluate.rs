@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use crate::{
+    allocation_penalty::Penalty, cost::Cost, problem::Problem, solution::Solution,
+    violation::Violation,
+};
+
+#[derive(Debug)]
+pub struct Evaluation {
+    pub solution: Solution,
+    pub cost: Cost,
+    pub violations: Vec<Violation>,
+}
+
+// Build a solution from cottage IDs in reservation order.
+// Assignments that collide with an earlier one or a block are reported and left out.
+pub fn evaluate_ids(problem: &Problem, ids: &[Option<usize>]) -> Evaluation {
+    let reservations = problem.reservations.reservations();
+    let cottage_index: HashMap<_, _> = problem
+        .cottages
+        .id
+        .iter()
+        .enumerate()
+        .map(|(index, &id)| (id, index))
+        .collect();
+
+    let mut solution = Solution::empty(problem);
+    let mut violations = Vec::new();
+
+    if ids.len() != reservations {
+        violations.push(Violation::LineCount {
+            expected: reservations,
+            found: ids.len(),
+        });
+    }
+
+    for reservation in 0..reservations {
+        let Some(id) = ids.get(reservation).copied().flatten() else {
+            if !problem.allow_unallocated {
+                violations.push(Violation::Unassigned { reservation });
+            }
+            continue;
+        };
+
+        let Some(&cottage) = cottage_index.get(&id) else {
+            violations.push(Violation::UnknownCottage { reservation, id });
+            continue;
+        };
+
+        let range = problem.occupied(reservation, cottage);
+        if let Some(other) = solution.is_taken_by(cottage, range.clone()).next() {
+            let stay = problem.reservations.range(reservation);
+            let overlap = problem.reservations.range(other);
+            if stay.start < overlap.end && overlap.start < stay.end {
+                violations.push(Violation::Overlap {
+                    reservation,
+                    other,
+                    cottage,
+                });
+            } else {
+                violations.push(Violation::Turnover {
+                    reservation,
+                    other,
+                    cottage,
+                });
+            }
+            continue;
+        }
+
+        if problem.cottages.is_blocked(cottage, range.clone()) {
+            violations.push(Violation::Blocked {
+                reservation,
+                cottage,
+            });
+            continue;
+        }
+
+        match problem.reservations.cottage_number[reservation] {
+            Some(fixed) if fixed != cottage => violations.push(Violation::FixedCottage {
+                reservation,
+                cottage,
+                fixed,
+            }),
+            _ if problem.allocation_penalty.get(cottage, reservation) == Penalty::Impossible => {
+                violations.push(Violation::Impossible {
+                    reservation,
+                    cottage,
+                })
+            }
+            _ => (),
+        }
+
+        solution.assign(cottage, reservation, range);
+    }
+
+    let cost = Cost::calculate(problem, &solution);
+
+    Evaluation {
+        solution,
+        cost,
+        violations,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{
+        allocation_penalty::AllocationPenalty,
+        problem::{Cottages, Reservations},
+    };
+
+    use super::*;
+
+    fn problem() -> Problem {
+        let mut cottages = Cottages::empty(3);
+        let mut reservations = Reservations::empty(3);
+        cottages.id = vec![10, 20, 30];
+        cottages.class = vec![1, 1, 0];
+        reservations.class = vec![1, 1, 1];
+        reservations.set_arrival(vec![0, 1, 5]);
+        reservations.set_stay(vec![3, 3, 1]);
+        reservations.cottage_number = vec![None, None, Some(1)];
+
+        Problem::new(cottages, reservations, 0)
+    }
+
+    #[test]
+    fn test_evaluate_valid() {
+        let problem = problem();
+        let evaluation = evaluate_ids(&problem, &[Some(10), Some(20), Some(20)]);
+
+        assert_eq!(evaluation.violations, vec![]);
+        assert!(evaluation.solution.unallocated().is_empty());
+        assert_eq!(
+            evaluation.cost,
+            Cost::calculate(&problem, &evaluation.solution)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_violations() {
+        let problem = problem();
+        let evaluation = evaluate_ids(&problem, &[Some(10), Some(10), Some(30)]);
+
+        assert_eq!(
+            evaluation.violations,
+            vec![
+                Violation::Overlap {
+                    reservation: 1,
+                    other: 0,
+                    cottage: 0
+                },
+                Violation::FixedCottage {
+                    reservation: 2,
+                    cottage: 2,
+                    fixed: 1
+                },
+            ]
+        );
+        assert_eq!(evaluation.solution.unallocated(), &[1]);
+    }
+
+    #[test]
+    fn test_evaluate_blocked() {
+        let mut problem = problem();
+        problem.cottages.blocks[1].push(1..2);
+        problem.allocation_penalty = Arc::new(AllocationPenalty::calculate(
+            &problem.cottages,
+            &problem.reservations,
+            problem.amenities.soft(),
+        ));
+
+        let evaluation = evaluate_ids(&problem, &[Some(10), Some(20), Some(20)]);
+        assert_eq!(
+            evaluation.violations,
+            vec![Violation::Blocked {
+                reservation: 1,
+                cottage: 1
+            }]
+        );
+        assert_eq!(evaluation.solution.unallocated(), &[1]);
+        assert_eq!(
+            evaluation.solution.validate(&problem),
+            Err(vec![Violation::Unassigned { reservation: 1 }])
+        );
+    }
+
+    #[test]
+    fn test_evaluate_unassigned() {
+        let problem = problem();
+        let evaluation = evaluate_ids(&problem, &[Some(40), None]);
+
+        assert_eq!(
+            evaluation.violations,
+            vec![
+                Violation::LineCount {
+                    expected: 3,
+                    found: 2
+                },
+                Violation::UnknownCottage {
+                    reservation: 0,
+                    id: 40
+                },
+                Violation::Unassigned { reservation: 1 },
+                Violation::Unassigned { reservation: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_evaluate_impossible() {
+        let problem = problem();
+        let evaluation = evaluate_ids(&problem, &[Some(30), Some(10), Some(20)]);
+
+        assert_eq!(
+            evaluation.violations,
+            vec![Violation::Impossible {
+                reservation: 0,
+                cottage: 2
+            }]
+        );
+    }
+    #[test]
+    fn test_evaluate_turnover() {
+        let mut problem = problem();
+        problem.cottages.turnover = vec![0, 2, 0];
+        problem.allocation_penalty = Arc::new(AllocationPenalty::calculate(
+            &problem.cottages,
+            &problem.reservations,
+            problem.amenities.soft(),
+        ));
+
+        // One free day between reservations 1 and 2, two are needed
+        let evaluation = evaluate_ids(&problem, &[Some(10), Some(20), Some(20)]);
+        assert!(evaluation.violations.contains(&Violation::Turnover {
+            reservation: 2,
+            other: 1,
+            cottage: 1
+        }));
+        assert_eq!(evaluation.solution.unallocated(), &[2]);
+
+        // Two free days are enough
+        let evaluation = evaluate_ids(&problem, &[Some(20), Some(10), Some(20)]);
+        assert_eq!(evaluation.violations, vec![]);
+    }
+}
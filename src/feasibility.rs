@@ -0,0 +1,453 @@
+use std::ops::Range;
+
+use crate::{allocation_penalty::Penalty, problem::Problem};
+
+// A reason why not every reservation can get a cottage, found before solving.
+// These are necessary conditions only, a problem that passes may still be infeasible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Infeasibility {
+    // Every cottage is ruled out for this reservation
+    NoCottage {
+        reservation: usize,
+    },
+    // More guests stay on these days than the cottages they may use can hold.
+    // The counts are of the worst day.
+    Oversubscribed {
+        days: Range<usize>,
+        reservations: usize,
+        cottages: usize,
+    },
+    // These reservations, all around the day, can only use fewer cottages
+    Crowded {
+        day: usize,
+        reservations: Vec<usize>,
+        cottages: Vec<usize>,
+    },
+}
+
+impl Infeasibility {
+    pub fn describe(&self, problem: &Problem) -> String {
+        match self {
+            Infeasibility::NoCottage { reservation } => format!(
+                "Reservation {} can not be assigned to any cottage",
+                problem.reservations.id[*reservation]
+            ),
+            Infeasibility::Oversubscribed {
+                days,
+                reservations,
+                cottages,
+            } => format!(
+                "Days {:?}: {} reservations stay but only {} cottages can take them",
+                days, reservations, cottages
+            ),
+            Infeasibility::Crowded {
+                day,
+                reservations,
+                cottages,
+            } => {
+                let reservation_ids: Vec<_> = reservations
+                    .iter()
+                    .map(|&x| problem.reservations.id[x].to_string())
+                    .collect();
+                let cottage_ids: Vec<_> = cottages
+                    .iter()
+                    .map(|&x| problem.cottages.id[x].to_string())
+                    .collect();
+                format!(
+                    "Day {}: reservations {} can only use cottages {} between them",
+                    day,
+                    reservation_ids.join(", "),
+                    cottage_ids.join(", ")
+                )
+            }
+        }
+    }
+}
+
+// Why a cottage is ruled out for a reservation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exclusion {
+    // The reservation is fixed to another cottage
+    FixedCottage { fixed: usize },
+    // The cottage is blocked during the stay or its turnover days
+    Blocked,
+    // The cottage lacks these hard amenities
+    Preference { missing: Vec<usize> },
+    // The cottage class is below the booked class
+    Class { class: usize },
+    // The cottage holds fewer guests than the party
+    Capacity { capacity: usize },
+    // Another reservation that stays at the same time can only use this cottage
+    Taken { other: usize },
+    // Ruled out by propagation through reservations that are themselves unplaceable
+    Propagated,
+}
+
+impl Exclusion {
+    pub fn describe(&self, problem: &Problem, reservation: usize) -> String {
+        match self {
+            Exclusion::FixedCottage { fixed } => format!(
+                "the reservation is fixed to cottage {}",
+                problem.cottages.id[*fixed]
+            ),
+            Exclusion::Blocked => "blocked during the stay or its turnover".to_string(),
+            Exclusion::Preference { missing } => {
+                let names: Vec<_> = missing
+                    .iter()
+                    .map(|&x| match problem.amenities.names().get(x) {
+                        Some(name) => format!("`{}`", name),
+                        None => format!("amenity {}", x),
+                    })
+                    .collect();
+                format!("no {}", names.join(", "))
+            }
+            Exclusion::Class { class } => format!(
+                "class {} is below the booked class {}",
+                class, problem.reservations.class[reservation]
+            ),
+            Exclusion::Capacity { capacity } => format!(
+                "holds {} guests, the party is {}",
+                capacity, problem.reservations.people[reservation]
+            ),
+            Exclusion::Taken { other } => format!(
+                "reservation {} stays at the same time and can only use this cottage",
+                problem.reservations.id[*other]
+            ),
+            Exclusion::Propagated => {
+                "ruled out by reservations that stay at the same time".to_string()
+            }
+        }
+    }
+}
+
+// The cottages a reservation can not use, grouped by the reason
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnosis {
+    pub reservation: usize,
+    pub exclusions: Vec<(Exclusion, Vec<usize>)>,
+}
+
+impl Diagnosis {
+    pub fn describe(&self, problem: &Problem) -> String {
+        format!(
+            "Reservation {} can not be assigned to any cottage:{}",
+            problem.reservations.id[self.reservation],
+            self.describe_exclusions(problem)
+        )
+    }
+
+    // A line per reason, each starting on a new line
+    pub fn describe_exclusions(&self, problem: &Problem) -> String {
+        let mut out = String::new();
+        for (exclusion, cottages) in &self.exclusions {
+            let ids: Vec<_> = cottages
+                .iter()
+                .map(|&x| problem.cottages.id[x].to_string())
+                .collect();
+            let label = if ids.len() == 1 {
+                "cottage"
+            } else {
+                "cottages"
+            };
+            out += &format!(
+                "\n  {} {}: {}",
+                label,
+                ids.join(", "),
+                exclusion.describe(problem, self.reservation)
+            );
+        }
+        out
+    }
+}
+
+// The first check of `AllocationPenalty::calculate` that rules the pair out,
+// or None if the pair is allowed
+pub fn exclusion(problem: &Problem, reservation: usize, cottage: usize) -> Option<Exclusion> {
+    let cottages = &problem.cottages;
+    let reservations = &problem.reservations;
+
+    if let Some(fixed) = reservations.cottage_number[reservation] {
+        if fixed != cottage {
+            return Some(Exclusion::FixedCottage { fixed });
+        }
+    }
+
+    let missing: Vec<_> = reservations.preference[reservation]
+        .difference(problem.amenities.soft())
+        .difference(&cottages.preference[cottage])
+        .iter()
+        .collect();
+    if !missing.is_empty() {
+        return Some(Exclusion::Preference { missing });
+    }
+
+    if cottages.is_blocked(cottage, problem.occupied(reservation, cottage)) {
+        return Some(Exclusion::Blocked);
+    }
+    if cottages.class[cottage] < reservations.class[reservation] {
+        return Some(Exclusion::Class {
+            class: cottages.class[cottage],
+        });
+    }
+    if cottages.capacity[cottage] < reservations.people[reservation] {
+        return Some(Exclusion::Capacity {
+            capacity: cottages.capacity[cottage],
+        });
+    }
+
+    if problem.allocation_penalty.get(cottage, reservation) != Penalty::Impossible {
+        return None;
+    }
+
+    // Left is propagation from a reservation that has only this cottage
+    let turnover = cottages.turnover[cottage];
+    let range = reservations.range(reservation);
+    reservations
+        .get_overlaps_range(range.start.saturating_sub(turnover)..range.end + turnover)
+        .find(|&other| {
+            other != reservation
+                && problem.allocation_penalty.possible_targets(other) == [cottage as u32]
+        })
+        .map(|other| Exclusion::Taken { other })
+        .or(Some(Exclusion::Propagated))
+}
+
+pub fn diagnose(problem: &Problem, reservation: usize) -> Diagnosis {
+    let mut exclusions: Vec<(Exclusion, Vec<usize>)> = Vec::new();
+    for cottage in 0..problem.cottages.cottages() {
+        let Some(exclusion) = exclusion(problem, reservation, cottage) else {
+            continue;
+        };
+        match exclusions.iter_mut().find(|(x, _)| *x == exclusion) {
+            Some((_, cottages)) => cottages.push(cottage),
+            None => exclusions.push((exclusion, vec![cottage])),
+        }
+    }
+
+    Diagnosis {
+        reservation,
+        exclusions,
+    }
+}
+
+pub fn check_feasibility(problem: &Problem) -> Vec<Infeasibility> {
+    let allocation_penalty = &problem.allocation_penalty;
+    let reservations = &problem.reservations;
+
+    let mut infeasibilities: Vec<_> = (0..reservations.reservations())
+        .filter(|&x| allocation_penalty.possible_targets(x).is_empty())
+        .map(|reservation| Infeasibility::NoCottage { reservation })
+        .collect();
+
+    // Reservations present on each day, including the turnover days after the stay
+    // in the cottage with the shortest turnover it may use.
+    // Those without any cottage are reported already.
+    let mut present = vec![Vec::new(); problem.timeslots()];
+    for reservation in 0..reservations.reservations() {
+        let Some(end) = allocation_penalty
+            .possible_targets(reservation)
+            .iter()
+            .map(|&cottage| problem.occupied(reservation, cottage as usize).end)
+            .min()
+        else {
+            continue;
+        };
+        let start = reservations.range(reservation).start;
+        for staying in &mut present[start..end] {
+            staying.push(reservation);
+        }
+    }
+
+    // Per day, the reservations need at least as many distinct cottages
+    let mut usable = vec![false; problem.cottages.cottages()];
+    let counts: Vec<(usize, usize)> = present
+        .iter()
+        .map(|staying| {
+            usable.fill(false);
+            for &reservation in staying {
+                for &cottage in allocation_penalty.possible_targets(reservation) {
+                    usable[cottage as usize] = true;
+                }
+            }
+            (staying.len(), usable.iter().filter(|&&x| x).count())
+        })
+        .collect();
+
+    // Consecutive oversubscribed days are reported together
+    let mut day = 0;
+    while day < counts.len() {
+        let start = day;
+        while day < counts.len() && counts[day].0 > counts[day].1 {
+            day += 1;
+        }
+        if day == start {
+            day += 1;
+            continue;
+        }
+
+        let (reservations, cottages) = counts[start..day]
+            .iter()
+            .copied()
+            .max_by_key(|(reservations, cottages)| reservations - cottages)
+            .unwrap();
+        infeasibilities.push(Infeasibility::Oversubscribed {
+            days: start..day,
+            reservations,
+            cottages,
+        });
+    }
+
+    // Crowds on days that are oversubscribed as a whole add nothing
+    let oversubscribed = |day: usize| {
+        infeasibilities.iter().any(|x| match x {
+            Infeasibility::Oversubscribed { days, .. } => days.contains(&day),
+            _ => false,
+        })
+    };
+    let crowded: Vec<_> = allocation_penalty
+        .crowds
+        .iter()
+        .filter(|crowd| !oversubscribed(crowd.day))
+        .map(|crowd| Infeasibility::Crowded {
+            day: crowd.day,
+            reservations: crowd.reservations.clone(),
+            cottages: crowd.cottages.clone(),
+        })
+        .collect();
+    infeasibilities.extend(crowded);
+
+    infeasibilities
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        amenity::AmenitySet,
+        problem::{Cottages, Reservations},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_feasible() {
+        let problem = Problem::empty(2, 2);
+        assert_eq!(check_feasibility(&problem), vec![]);
+    }
+
+    #[test]
+    fn test_no_cottage() {
+        let mut cottages = Cottages::empty(2);
+        cottages.capacity = vec![2, 4];
+        let mut reservations = Reservations::empty(2);
+        reservations.people = vec![6, 4];
+        reservations.set_arrival(vec![0, 1]);
+
+        let problem = Problem::new(cottages, reservations, 0);
+        assert_eq!(
+            check_feasibility(&problem),
+            vec![Infeasibility::NoCottage { reservation: 0 }]
+        );
+        assert!(!problem.allocation_penalty.reallocatable[0]);
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let mut cottages = Cottages::empty(6);
+        cottages.id = vec![1, 2, 3, 4, 5, 6];
+        cottages.capacity = vec![4, 2, 4, 4, 4, 4];
+        cottages.class = vec![1, 1, 0, 1, 1, 1];
+        cottages.preference = vec![[0].into_iter().collect(); 6];
+        cottages.preference[3] = AmenitySet::default();
+        cottages.blocks[4].push(1..2);
+
+        let mut reservations = Reservations::empty(2);
+        reservations.people = vec![4, 4];
+        reservations.class = vec![1, 1];
+        reservations.preference = vec![[0].into_iter().collect(), AmenitySet::default()];
+        reservations.set_stay(vec![2, 1]);
+        reservations.cottage_number = vec![None, Some(5)];
+
+        let problem = Problem::new(cottages, reservations, 0);
+
+        // Cottage 1 is left for reservation 0, the rest is ruled out one way or another
+        assert_eq!(
+            diagnose(&problem, 0).exclusions,
+            vec![
+                (Exclusion::Capacity { capacity: 2 }, vec![1]),
+                (Exclusion::Class { class: 0 }, vec![2]),
+                (Exclusion::Preference { missing: vec![0] }, vec![3]),
+                (Exclusion::Blocked, vec![4]),
+                (Exclusion::Taken { other: 1 }, vec![5]),
+            ]
+        );
+        assert_eq!(
+            diagnose(&problem, 1).exclusions,
+            vec![(Exclusion::FixedCottage { fixed: 5 }, vec![0, 1, 2, 3, 4])]
+        );
+    }
+
+    #[test]
+    fn test_oversubscribed_turnover() {
+        // 10 and 11 stay on days 0 and 1, 12 on day 2.
+        // The turnover day after 10 and 11 leaves no cottage for 12
+        let mut cottages = Cottages::empty(2);
+        cottages.turnover = vec![1, 1];
+        let mut reservations = Reservations::empty(3);
+        reservations.id = vec![10, 11, 12];
+        reservations.set_arrival(vec![0, 0, 2]);
+        reservations.set_stay(vec![2, 2, 1]);
+
+        let problem = Problem::new(cottages.clone(), reservations.clone(), 0);
+        assert_eq!(
+            check_feasibility(&problem),
+            vec![Infeasibility::Oversubscribed {
+                days: 2..3,
+                reservations: 3,
+                cottages: 2
+            }]
+        );
+
+        // Without turnover 12 takes the cottage of 10 or 11
+        cottages.turnover = vec![0, 0];
+        let problem = Problem::new(cottages, reservations, 0);
+        assert_eq!(check_feasibility(&problem), vec![]);
+    }
+
+    #[test]
+    fn test_crowded() {
+        // Reservations 0, 1 and 2 need class 1, which only cottages 0 and 1 have.
+        // Four cottages for four reservations, so the day is not oversubscribed as a whole.
+        let mut cottages = Cottages::empty(4);
+        cottages.class = vec![1, 1, 0, 0];
+        let mut reservations = Reservations::empty(4);
+        reservations.class = vec![1, 1, 1, 0];
+
+        let problem = Problem::new(cottages, reservations, 0);
+        assert_eq!(
+            check_feasibility(&problem),
+            vec![Infeasibility::Crowded {
+                day: 0,
+                reservations: vec![0, 1, 2],
+                cottages: vec![0, 1]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_oversubscribed() {
+        // Three stays share days 2 and 3, there are two cottages
+        let mut reservations = Reservations::empty(4);
+        reservations.set_arrival(vec![0, 2, 1, 6]);
+        reservations.set_stay(vec![4, 3, 3, 1]);
+
+        let problem = Problem::new(Cottages::empty(2), reservations, 0);
+        assert_eq!(
+            check_feasibility(&problem),
+            vec![Infeasibility::Oversubscribed {
+                days: 2..4,
+                reservations: 3,
+                cottages: 2
+            }]
+        );
+    }
+}
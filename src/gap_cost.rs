@@ -1,4 +1,7 @@
-use crate::{problem::Problem, solution::Solution};
+use crate::{
+    problem::{BoundaryPolicy, Problem},
+    solution::Solution,
+};
 
 #[derive(Default)]
 struct GapFoldState {
@@ -40,12 +43,12 @@ fn fold_gaps(state: GapFoldState, cell: (usize, usize)) -> GapFoldState {
 
 #[inline(never)]
 fn calculate_gaps_single(timetable: &[Option<usize>], phase: usize) -> GapFoldState {
-    return timetable
+    timetable
         .iter()
         .map(|x| x.is_none() as usize)
         .enumerate()
         .map(|(date, cell)| ((date + phase).rem_euclid(7), cell))
-        .fold(GapFoldState::default(), fold_gaps);
+        .fold(GapFoldState::default(), fold_gaps)
 }
 
 pub fn calculate_cottage_gaps(
@@ -67,27 +70,39 @@ pub fn calculate_cottage_gaps2(
 ) -> (usize, usize, usize) {
     let timetable = solution.get_gap_timetable(cottage);
     let phase = problem.phase;
+    let horizon = solution.horizon();
 
     let mut gaps = 0;
     let mut fr_th_gaps = 0;
     let mut legionella_gaps = 0;
 
-    let mut i = 0;
-    while i < timetable.len() {
+    // Gaps never cross the horizon, the gap timetable is 0 outside of it
+    let mut i = horizon.start;
+    while i < horizon.end {
         let gap = timetable[i];
         if gap == 0 {
             i += 1;
             continue;
         }
 
+        let boundary = i == horizon.start || i + gap == horizon.end;
+        let open_ended = match problem.boundary {
+            BoundaryPolicy::Ignore if boundary => {
+                i += gap;
+                continue;
+            }
+            BoundaryPolicy::OpenEnded => boundary,
+            _ => false,
+        };
+
         gaps += 1;
 
         // 0: fri, 1: sat, 2: sun... 6: thu
         let phase_day = (i + phase).rem_euclid(7);
         let inverse_phase_day = (7 - phase_day).rem_euclid(7);
-        fr_th_gaps += (gap.saturating_sub(inverse_phase_day) / 7) as usize;
+        fr_th_gaps += gap.saturating_sub(inverse_phase_day) / 7;
 
-        legionella_gaps += (gap >= 21) as usize;
+        legionella_gaps += (gap >= 21 || open_ended) as usize;
 
         i += gap;
     }
@@ -118,7 +133,12 @@ pub fn calculate_gaps2(problem: &Problem, solution: &Solution) -> (usize, usize,
 
 #[cfg(test)]
 mod test {
-    use crate::problem::Problem;
+    use std::sync::Arc;
+
+    use crate::{
+        allocation_penalty::AllocationPenalty,
+        problem::{Cottages, Problem, Reservations},
+    };
 
     use super::*;
 
@@ -126,7 +146,7 @@ mod test {
     fn calculate_gap_assign() {
         let size = 1;
         let mut problem = Problem::empty(size, size);
-        problem.reservations.stay = vec![5; size];
+        problem.reservations.set_stay(vec![5; size]);
         let mut solution = Solution::empty(&problem);
         assert_eq!(calculate_gaps(&problem, &solution).0, 1);
 
@@ -159,7 +179,7 @@ mod test {
     fn calculate_gap_2_assign() {
         let size = 2;
         let mut problem = Problem::empty(size, size);
-        problem.reservations.stay = vec![5; size];
+        problem.reservations.set_stay(vec![5; size]);
         let mut solution = Solution::empty(&problem);
         assert_eq!(calculate_gaps(&problem, &solution).0, 2);
 
@@ -215,8 +235,7 @@ mod test {
     #[test]
     fn calculate_gap_unassign() {
         let mut problem = Problem::empty(1, 5);
-        problem.reservations.arrival = (0..5).collect();
-        problem.reservations.update();
+        problem.reservations.set_arrival((0..5).collect());
         let mut solution = Solution::empty(&problem);
 
         // _____
@@ -257,7 +276,7 @@ mod test {
     fn calculate_legionella_gap() {
         let size = 1;
         let mut problem = Problem::empty(size, size);
-        problem.reservations.arrival = vec![42];
+        problem.reservations.set_arrival(vec![42]);
         let mut solution = Solution::empty(&problem);
 
         assert_eq!(calculate_gaps(&problem, &solution).2, 1);
@@ -272,13 +291,117 @@ mod test {
         assert_eq!(calculate_gaps(&problem, &solution).2, 0);
     }
 
+    #[test]
+    fn calculate_boundary_gaps() {
+        let mut problem = Problem::empty(1, 2);
+        problem.reservations.set_arrival(vec![0, 6]);
+        problem.reservations.set_stay(vec![3, 6]);
+        problem.horizon = Some(1..10);
+
+        // Days 1..10 inside the horizon
+        // ###___######
+        //  ##___####
+        let mut solution = Solution::empty(&problem);
+        solution.assign(0, 0, 0..3);
+        solution.assign(0, 1, 6..12);
+        assert_eq!(calculate_gaps(&problem, &solution), (1, 0, 0));
+
+        // The start of the horizon is now free
+        // ______######
+        //  _____####
+        solution.unassign(0, 0, 0..3);
+
+        problem.boundary = BoundaryPolicy::Count;
+        assert_eq!(calculate_gaps(&problem, &solution), (1, 0, 0));
+
+        problem.boundary = BoundaryPolicy::Ignore;
+        assert_eq!(calculate_gaps(&problem, &solution), (0, 0, 0));
+
+        problem.boundary = BoundaryPolicy::OpenEnded;
+        assert_eq!(calculate_gaps(&problem, &solution), (1, 0, 1));
+
+        // A gap inside the horizon is costed the same under every policy
+        // ______######
+        //  _#___####
+        problem.reservations.set_arrival(vec![2, 6]);
+        problem.reservations.set_stay(vec![1, 6]);
+        solution.assign(0, 0, 2..3);
+        for boundary in [
+            BoundaryPolicy::Count,
+            BoundaryPolicy::Ignore,
+            BoundaryPolicy::OpenEnded,
+        ] {
+            problem.boundary = boundary;
+            let (gaps, _, _) = calculate_gaps(&problem, &solution);
+            let expected = match boundary {
+                BoundaryPolicy::Ignore => 1,
+                _ => 2,
+            };
+            assert_eq!(gaps, expected);
+        }
+    }
+
+    #[test]
+    fn calculate_blocked_gaps() {
+        let mut reservations = Reservations::empty(2);
+        reservations.set_arrival(vec![0, 28]);
+        reservations.set_stay(vec![2, 2]);
+        let mut problem = Problem::new(Cottages::empty(1), reservations, 0);
+
+        // A 26 day gap is a legionella gap
+        let mut solution = Solution::empty(&problem);
+        solution.assign(0, 0, 0..2);
+        solution.assign(0, 1, 28..30);
+        assert_eq!(calculate_gaps(&problem, &solution).0, 1);
+        assert_eq!(calculate_gaps(&problem, &solution).2, 1);
+
+        // Blocked days are not free, the gaps before and after are short
+        problem.cottages.blocks[0].push(10..20);
+        problem.allocation_penalty = Arc::new(AllocationPenalty::calculate(
+            &problem.cottages,
+            &problem.reservations,
+            problem.amenities.soft(),
+        ));
+        let mut solution = Solution::empty(&problem);
+        solution.assign(0, 0, 0..2);
+        solution.assign(0, 1, 28..30);
+        assert_eq!(calculate_gaps(&problem, &solution).0, 2);
+        assert_eq!(calculate_gaps(&problem, &solution).2, 0);
+        assert!(solution.validate(&problem).is_ok());
+
+        solution.unassign(0, 0, 0..2);
+        assert_eq!(solution.get_gap_timetable(0)[0], 10);
+        assert_eq!(solution.get_gap_timetable(0)[20], 8);
+    }
+
+    #[test]
+    fn calculate_turnover_gaps() {
+        let mut reservations = Reservations::empty(2);
+        reservations.set_arrival(vec![0, 3]);
+        reservations.set_stay(vec![2, 2]);
+        let mut cottages = Cottages::empty(1);
+        cottages.turnover = vec![1];
+        let problem = Problem::new(cottages, reservations, 0);
+
+        // The day between the stays is the turnover, not an idle gap
+        let mut solution = Solution::empty(&problem);
+        solution.assign(0, 0, problem.occupied(0, 0));
+        solution.assign(0, 1, problem.occupied(1, 0));
+        assert_eq!(calculate_gaps(&problem, &solution), (0, 0, 0));
+        assert!(solution.validate(&problem).is_ok());
+
+        solution.unassign(0, 0, problem.occupied(0, 0));
+        assert_eq!(calculate_gaps(&problem, &solution), (1, 0, 0));
+        assert_eq!(solution.get_gap_timetable(0)[..3], [3, 3, 3]);
+    }
+
     #[test]
     fn calculate_fr_th_gap() {
         let size = 1;
         let mut problem = Problem::empty(size, size);
         problem.phase = 5;
 
-        problem.reservations.arrival = vec![18];
+        problem.reservations.set_arrival(vec![18]);
         let mut solution = Solution::empty(&problem);
         solution.assign(0, 0, 0..2);
 
@@ -1,11 +1,47 @@
 use fastrand::Rng;
 
 use crate::{
+    feasibility::{diagnose, Diagnosis},
     modification::{Chain, Modification},
     problem::Problem,
     solution::Solution,
 };
 
+// Repair without unallocated reservations gave up,
+// with the reservations that were left without a cottage, sorted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairError {
+    pub reservations: Vec<usize>,
+}
+
+impl RepairError {
+    // Diagnosed only here, repair gives up in the annealing loop as well
+    pub fn diagnoses(&self, problem: &Problem) -> Vec<Diagnosis> {
+        self.reservations
+            .iter()
+            .map(|&reservation| diagnose(problem, reservation))
+            .collect()
+    }
+
+    pub fn describe(&self, problem: &Problem) -> String {
+        self.diagnoses(problem)
+            .iter()
+            .map(|diagnosis| {
+                let mut out = format!(
+                    "Reservation {} could not be placed; no allowed cottage is free",
+                    problem.reservations.id[diagnosis.reservation]
+                );
+                if !diagnosis.exclusions.is_empty() {
+                    out += ". Not allowed:";
+                    out += &diagnosis.describe_exclusions(problem);
+                }
+                out
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
 #[derive(Debug)]
 pub struct Instance {
     pub problem: Problem,
@@ -16,34 +52,53 @@ pub struct Instance {
 
 impl Instance {
     #[inline(always)]
-    fn rand(&self, slice: &[usize]) -> usize {
+    fn rand<T>(&self, slice: &[T]) -> usize {
         self.rng.usize(..slice.len())
     }
 
     #[inline(always)]
-    fn rand_index(&self, slice: &[usize]) -> usize {
+    fn rand_index<T: Copy>(&self, slice: &[T]) -> T {
         let index = self.rand(slice);
         unsafe { *slice.get_unchecked(index) }
     }
 
     pub fn new(problem: Problem, solution: Solution) -> Self {
+        Self::with_rng(problem, solution, Rng::new())
+    }
+
+    pub fn with_seed(problem: Problem, solution: Solution, seed: u64) -> Self {
+        Self::with_rng(problem, solution, Rng::with_seed(seed))
+    }
+
+    fn with_rng(problem: Problem, solution: Solution, rng: Rng) -> Self {
         Self {
             problem,
             solution,
             chain: Chain::new(),
-            rng: Rng::new(),
+            rng,
         }
     }
 
+    // Uniform draw from [0, 1), all randomness of a run goes through `rng`
+    pub fn rand_f64(&self) -> f64 {
+        self.rng.f64()
+    }
+
     pub fn neighbor(&mut self) {
         let reservation =
             self.rand_index(&self.problem.allocation_penalty.reallocatable_reservations[..]);
 
-        let modification = Modification::unassign(reservation, &self.problem, &self.solution);
+        // A reservation left out of a partial plan only gets a new chance to be placed
+        if self.solution.mapping_single(reservation).is_some() {
+            let modification = Modification::unassign(reservation, &self.problem, &self.solution);
 
-        self.chain.add(modification);
-        self.chain.progress(&self.problem, &mut self.solution);
-        self.repair();
+            self.chain.add(modification);
+            self.chain.progress(&self.problem, &mut self.solution);
+        }
+        // Whatever was undone can always be put back
+        if self.repair().is_err() {
+            self.reject_chain();
+        }
     }
 
     pub fn get_random_cottage(&mut self, reservation: usize) -> usize {
@@ -52,7 +107,7 @@ impl Instance {
             .allocation_penalty
             .possible_targets(reservation);
 
-        self.rand_index(cottages)
+        self.rand_index(cottages) as usize
     }
 
     pub fn accept_chain(&mut self) {
@@ -71,6 +126,14 @@ impl Instance {
 
     fn repair_single(&mut self) {
         let reservation = self.random_unallocated();
+        if self
+            .problem
+            .allocation_penalty
+            .possible_targets(reservation)
+            .is_empty()
+        {
+            return;
+        }
         let cottage = self.get_random_cottage(reservation);
 
         let modification = Modification::assign(reservation, cottage, &self.problem);
@@ -83,10 +146,8 @@ impl Instance {
             .sum();
         // .fold(0f64, f64::max);
 
-        if prio_old > prio_new {
-            if self.rng.f64() > 0.005 {
-                return;
-            }
+        if prio_old > prio_new && self.rng.f64() > 0.005 {
+            return;
         }
 
         // if self
@@ -118,6 +179,14 @@ impl Instance {
 
     fn repair_single_force(&mut self) {
         let reservation = self.random_unallocated();
+        if self
+            .problem
+            .allocation_penalty
+            .possible_targets(reservation)
+            .is_empty()
+        {
+            return;
+        }
         let cottage = self.get_random_cottage(reservation);
 
         let modification = Modification::assign(reservation, cottage, &self.problem);
@@ -131,12 +200,44 @@ impl Instance {
         self.chain.progress(&self.problem, &mut self.solution);
     }
 
-    pub fn repair(&mut self) {
+    pub fn repair(&mut self) -> Result<(), RepairError> {
+        if self.solution.unallocated().is_empty() {
+            return Ok(());
+        }
+
         self.repair_single_force();
 
+        // Without a bound an over-subscribed problem would never be repaired,
+        // what is left after a few tries per reservation stays unallocated
+        if self.problem.allow_unallocated {
+            for _ in 0..4 * self.solution.unallocated().len() {
+                if self.solution.unallocated().is_empty() {
+                    break;
+                }
+                self.repair_single();
+            }
+            return Ok(());
+        }
+
+        // Every reservation has to be placed, so give up only
+        // after a few tries per reservation that place no more than before
+        let limit = 4 * self.problem.reservations.reservations();
+        let mut fewest = self.solution.unallocated().len();
+        let mut tries = 0;
         while !self.solution.unallocated().is_empty() {
+            if tries == limit {
+                let mut reservations = self.solution.unallocated().to_vec();
+                reservations.sort_unstable();
+                return Err(RepairError { reservations });
+            }
             self.repair_single();
+            tries += 1;
+            if self.solution.unallocated().len() < fewest {
+                fewest = self.solution.unallocated().len();
+                tries = 0;
+            }
         }
+        Ok(())
     }
 }
 
@@ -150,9 +251,13 @@ mod test {
     fn test_neighbor() {
         let problem = Problem::empty(2, 1);
         let solution = Solution::naive(&problem);
-        let mut instance = Instance::new(problem, solution);
+        let mut instance = Instance::with_seed(problem, solution, 3);
         instance.neighbor();
-        assert_eq!(instance.solution.mapping(), vec![Some(1)]);
+        assert!(!instance.chain.is_empty());
+
+        // Either cottage can be drawn, the chain has to undo and redo whichever it was
+        let moved = instance.solution.mapping().to_vec();
+        assert!(moved == vec![Some(0)] || moved == vec![Some(1)]);
         instance
             .chain
             .regress(&instance.problem, &mut instance.solution);
@@ -160,7 +265,7 @@ mod test {
         instance
             .chain
             .progress(&instance.problem, &mut instance.solution);
-        assert_eq!(instance.solution.mapping(), vec![Some(1)]);
+        assert_eq!(instance.solution.mapping(), moved);
         instance
             .chain
             .regress(&instance.problem, &mut instance.solution);
@@ -171,9 +276,13 @@ mod test {
     fn test_neighbor_evicted() {
         let problem = Problem::empty(2, 2);
         let solution = Solution::naive(&problem);
-        let mut instance = Instance::new(problem, solution);
+        let mut instance = Instance::with_seed(problem, solution, 3);
         instance.neighbor();
-        assert_eq!(instance.solution.mapping(), vec![Some(1), Some(0)]);
+        assert!(!instance.chain.is_empty());
+
+        // Either nothing moved in the end, or the reservations swapped cottages
+        let moved = instance.solution.mapping().to_vec();
+        assert!(moved == vec![Some(0), Some(1)] || moved == vec![Some(1), Some(0)]);
         instance
             .chain
             .regress(&instance.problem, &mut instance.solution);
@@ -181,7 +290,7 @@ mod test {
         instance
             .chain
             .progress(&instance.problem, &mut instance.solution);
-        assert_eq!(instance.solution.mapping(), vec![Some(1), Some(0)]);
+        assert_eq!(instance.solution.mapping(), moved);
         instance
             .chain
             .regress(&instance.problem, &mut instance.solution);
@@ -201,8 +310,9 @@ mod test {
         let solution = Solution::empty(&problem);
         let mut instance = Instance::new(problem, solution);
 
-        instance.repair();
+        instance.repair().unwrap();
         assert!(instance.solution.unallocated().is_empty());
+        assert_eq!(instance.solution.validate(&instance.problem), Ok(()));
     }
 
     // #[test]
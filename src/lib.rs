@@ -1,10 +1,18 @@
 pub mod allocation_penalty;
+pub mod amenity;
+pub mod cooling;
 pub mod cost;
+pub mod evaluate;
+pub mod feasibility;
 pub mod gap_cost;
 pub mod instance;
 pub mod modification;
+pub mod overlap_index;
+pub mod preference_cost;
 pub mod problem;
 pub mod read;
+pub mod report;
 pub mod simulated_annealing;
 pub mod solution;
 pub mod upgrade_cost;
+pub mod violation;
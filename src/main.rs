@@ -1,44 +1,518 @@
-use std::io::Write;
+use std::{io::Write, str::FromStr};
 
-use or_challenge::{read::read_problem_json, simulated_annealing::run_simulated_annealing};
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use clap::{Args, Parser, Subcommand};
+use or_challenge::{
+    cooling::{CoolingConfig, Schedule},
+    cost::Cost,
+    evaluate::evaluate_ids,
+    feasibility::{check_feasibility, diagnose, Infeasibility},
+    problem::{BoundaryPolicy, Problem},
+    read::{
+        read_problem_csv, read_problem_json, read_problem_xlsx, read_solution, read_weights_json,
+        ReadError, ReadOptions,
+    },
+    report::Report,
+    simulated_annealing::{
+        run_simulated_annealing, run_simulated_annealing_from, thread_seed, SolverConfig,
+    },
+    solution::Solution,
+};
 
-fn main() {
-    let problem = read_problem_json(
-        "./cottages.json".to_string(),
-        "./reservations.json".to_string(),
-    );
+#[derive(Parser)]
+#[command(about = "Simulated annealing solver for the cottage allocation problem")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Solve a problem and write the best assignment found
+    Solve(SolveArgs),
+    /// Print a cost breakdown of an existing solution file
+    Evaluate(SolutionArgs),
+    /// Check an existing solution file for constraint violations
+    Validate(SolutionArgs),
+    /// Print statistics about a problem
+    Inspect(InputArgs),
+}
+
+#[derive(Args)]
+struct InputArgs {
+    /// Path to the cottages JSON file, or CSV file if it ends in `.csv`
+    #[arg(long, default_value = "./cottages.json")]
+    cottages: String,
+
+    /// Path to the reservations JSON file, or CSV file if it ends in `.csv`
+    #[arg(long, default_value = "./reservations.json")]
+    reservations: String,
+
+    /// Path to an XLSX workbook with `Cottages` and `Reservations` sheets,
+    /// read instead of `--cottages` and `--reservations`
+    #[arg(long, conflicts_with_all = ["cottages", "reservations"])]
+    workbook: Option<String>,
+
+    /// Path to a JSON file with objective weights
+    #[arg(long)]
+    weights: Option<String>,
+
+    /// Timezone in which arrival days start at midnight, e.g. Europe/Amsterdam
+    #[arg(long, default_value = "UTC")]
+    timezone: Tz,
+
+    /// First day of the planning horizon (YYYY-MM-DD), defaults to the earliest arrival
+    #[arg(long)]
+    horizon_start: Option<NaiveDate>,
+
+    /// First day after the planning horizon (YYYY-MM-DD), defaults to the day after the latest arrival
+    #[arg(long)]
+    horizon_end: Option<NaiveDate>,
+
+    /// Cost of gaps touching the horizon: count, ignore or open-ended
+    #[arg(long, default_value = "count")]
+    boundary: BoundaryPolicy,
+
+    /// Comma-separated amenity columns, defaults to every cottage column that is
+    /// not a field and only holds 0 or 1. Other columns are ignored with a warning
+    #[arg(long, value_delimiter = ',')]
+    amenities: Option<Vec<String>>,
+
+    /// Comma-separated amenities that are wishes: a cottage without them costs
+    /// `preferences` per amenity instead of being ruled out
+    #[arg(long, value_delimiter = ',')]
+    soft_amenities: Vec<String>,
+
+    /// Path to a JSON or CSV file of cottage blocks (`Cottage`, `First Day`, `Last Day`).
+    /// A workbook's `Blocks` sheet is read by default
+    #[arg(long)]
+    blocks: Option<String>,
+
+    /// Comma-separated `<class>=<days>`: free days a cottage of that class needs
+    /// between a departure and the next arrival, e.g. `3=1,4=1`
+    #[arg(long, value_delimiter = ',')]
+    turnover: Vec<Turnover>,
+
+    /// Allow reservations without a cottage, each costs the `unallocated` weight.
+    /// Without it, a problem that can not place every reservation is rejected
+    #[arg(long)]
+    allow_unallocated: bool,
+}
+
+#[derive(Args)]
+struct SolveArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Where to write the solution, defaults to `solution_<objective>`
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Number of solver threads, defaults to the number of physical CPUs
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// Wall-clock limit per thread in seconds
+    #[arg(long, default_value_t = 3000.0)]
+    time_limit: f64,
+
+    /// Number of annealing iterations per thread
+    #[arg(long, default_value_t = 1500000000)]
+    iterations: u64,
 
-    // run_simulated_annealing(problem);
+    /// Number of iterations between checks of the time limit
+    #[arg(long, default_value_t = 10000)]
+    check_interval: u64,
 
-    let cpus = num_cpus::get_physical();
+    /// Cooling schedule: linear, geometric, logarithmic, lundy-mees or time
+    #[arg(long, default_value = "linear")]
+    cooling: Schedule,
+
+    /// Starting temperature, or `auto` to calibrate from sampled moves
+    #[arg(long, default_value = "auto")]
+    start_temperature: Temperature,
+
+    /// Final temperature, or `auto` to calibrate from sampled moves
+    #[arg(long, default_value = "0")]
+    end_temperature: Temperature,
+
+    /// Share of sampled uphill moves the calibrated starting temperature accepts
+    #[arg(long, default_value_t = 0.5)]
+    acceptance: f64,
+
+    /// Number of neighbor moves sampled to calibrate temperatures
+    #[arg(long, default_value_t = 2000)]
+    calibration_samples: usize,
+
+    /// Start from an earlier solution file instead of an empty solution
+    #[arg(long)]
+    warm_start: Option<String>,
+
+    /// Write `<reservation ID> <cottage ID>` per line instead of only the cottage ID,
+    /// so the file can warm start a run on changed reservations
+    #[arg(long)]
+    with_ids: bool,
+
+    /// Master seed, every thread derives its own seed from it. Random if omitted
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Args)]
+struct SolutionArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Solution file with one cottage ID per line, in reservation order,
+    /// or `<reservation ID> <cottage ID>` per line.
+    /// Empty or `-` cottage IDs mark unassigned reservations
+    solution: String,
+}
+
+// A fixed temperature, or `None` when it should be calibrated
+#[derive(Clone, Copy)]
+struct Temperature(Option<f64>);
+
+impl FromStr for Temperature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self(None)),
+            s => s.parse().map(|x| Self(Some(x))).map_err(|e| format!("{e}")),
+        }
+    }
+}
+
+// Turnover days for one cottage class
+#[derive(Clone, Copy)]
+struct Turnover {
+    class: usize,
+    days: usize,
+}
+
+impl FromStr for Turnover {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, days) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected `<class>=<days>`, found `{s}`"))?;
+        Ok(Self {
+            class: class.trim().parse().map_err(|e| format!("class: {e}"))?,
+            days: days.trim().parse().map_err(|e| format!("days: {e}"))?,
+        })
+    }
+}
+
+// Input errors end the program with a message instead of a panic
+fn or_exit<T>(result: Result<T, ReadError>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("Error: {}", error);
+        std::process::exit(1);
+    })
+}
+
+fn read_problem(input: &InputArgs) -> Problem {
+    let options = ReadOptions {
+        timezone: input.timezone,
+        horizon_start: input.horizon_start,
+        horizon_end: input.horizon_end,
+        amenities: input.amenities.clone(),
+        soft_amenities: input.soft_amenities.clone(),
+        blocks: input.blocks.clone(),
+        turnover: input.turnover.iter().map(|x| (x.class, x.days)).collect(),
+    };
+    let is_csv = |path: &str| path.to_lowercase().ends_with(".csv");
+    let problem = match &input.workbook {
+        Some(workbook) => read_problem_xlsx(workbook.clone(), &options),
+        None if is_csv(&input.cottages) && is_csv(&input.reservations) => {
+            read_problem_csv(input.cottages.clone(), input.reservations.clone(), &options)
+        }
+        None => read_problem_json(input.cottages.clone(), input.reservations.clone(), &options),
+    };
+    let mut problem = or_exit(problem);
+    if !problem.ignored_columns.is_empty() {
+        eprintln!(
+            "Warning: ignored columns that are not an amenity: {}",
+            problem.ignored_columns.join(", ")
+        );
+    }
+    if !problem.skipped.is_empty() {
+        eprintln!(
+            "Warning: skipped {} reservations outside the planning horizon: {}",
+            problem.skipped.len(),
+            join_ids(&problem.skipped)
+        );
+    }
+    problem.boundary = input.boundary;
+    problem.allow_unallocated = input.allow_unallocated;
+    if let Some(weights) = &input.weights {
+        problem.weights = or_exit(read_weights_json(weights.clone()));
+    }
+    problem
+}
+
+// Reservations without a cottage come with the reason for every cottage,
+// so the data can be fixed at the source
+fn join_ids(ids: &[usize]) -> String {
+    ids.iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Cottage IDs in reservation order, lines that were left out are warned about
+fn read_solution_file(path: String, problem: &Problem) -> Vec<Option<usize>> {
+    let file = or_exit(read_solution(path.clone(), &problem.reservations));
+    if !file.unknown.is_empty() {
+        eprintln!(
+            "Warning: ignored {} unknown reservations in {}: {}",
+            file.unknown.len(),
+            path,
+            join_ids(&file.unknown)
+        );
+    }
+    if !file.duplicates.is_empty() {
+        eprintln!(
+            "Warning: ignored repeated lines of {} reservations in {}, the first is used: {}",
+            file.duplicates.len(),
+            path,
+            join_ids(&file.duplicates)
+        );
+    }
+    file.cottages
+}
+
+fn print_infeasibilities(problem: &Problem, infeasibilities: &[Infeasibility]) {
+    for infeasibility in infeasibilities {
+        match infeasibility {
+            Infeasibility::NoCottage { reservation } => {
+                println!("{}", diagnose(problem, *reservation).describe(problem))
+            }
+            _ => println!("{}", infeasibility.describe(problem)),
+        }
+    }
+}
+
+fn solve(args: SolveArgs) {
+    let problem = read_problem(&args.input);
+
+    let infeasibilities = check_feasibility(&problem);
+    print_infeasibilities(&problem, &infeasibilities);
+    if !infeasibilities.is_empty() && !problem.allow_unallocated {
+        eprintln!(
+            "Error: not every reservation can be placed, \
+             use --allow-unallocated to solve for a partial plan"
+        );
+        std::process::exit(1);
+    }
+    let cpus = args.threads.unwrap_or_else(num_cpus::get_physical);
+    let config = SolverConfig {
+        time_limit: std::time::Duration::from_secs_f64(args.time_limit),
+        iterations: args.iterations,
+        check_interval: args.check_interval,
+        seed: args.seed.unwrap_or_else(|| fastrand::u64(..)),
+        cooling: CoolingConfig {
+            schedule: args.cooling,
+            start: args.start_temperature.0,
+            end: args.end_temperature.0,
+            samples: args.calibration_samples,
+            acceptance: args.acceptance,
+        },
+    };
+    println!("Master seed: {}", config.seed);
+
+    let warm_start = args
+        .warm_start
+        .map(|path| read_solution_file(path, &problem));
 
     let now = std::time::Instant::now();
     let mut handles = Vec::new();
     for i in 0..cpus {
         let x = problem.clone();
-        let handle = std::thread::spawn(move || run_simulated_annealing(x, i));
+        let config = config.clone();
+        let warm_start = warm_start.clone();
+        let handle = std::thread::spawn(move || match warm_start {
+            Some(ids) => {
+                let solution = Solution::warm_start(&x, &ids);
+                println!(
+                    "T{}: warm start with {} reservations to repair",
+                    i,
+                    solution.unallocated().len()
+                );
+                run_simulated_annealing_from(x, solution, i, &config)
+            }
+            None => run_simulated_annealing(x, i, &config),
+        });
         handles.push(handle);
     }
 
     let mut instances = Vec::new();
-    for handle in handles {
-        let instance = handle.join().unwrap();
-        let objective = or_challenge::cost::Cost::calculate_instance(&instance).objective();
+    for (i, handle) in handles.into_iter().enumerate() {
+        let instance = match handle.join().unwrap() {
+            Ok(instance) => instance,
+            Err(error) => {
+                println!("Thread {} could not place every reservation:", i);
+                println!("{}", error.describe(&problem));
+                continue;
+            }
+        };
+        if let Err(violations) = instance.solution.validate(&instance.problem) {
+            println!("Discarding invalid solution of thread {}:", i);
+            for violation in &violations {
+                println!("{}", violation.describe(&problem));
+            }
+            continue;
+        }
+        let objective = Cost::calculate_instance(&instance).objective(&problem.weights);
+
+        instances.push((instance, objective, i));
+    }
+
+    let Some(best) = instances.iter().min_by_key(|x| (x.1, x.2)) else {
+        println!("No thread produced a valid solution");
+        std::process::exit(1);
+    };
+    println!(
+        "Best objective {} found by thread {} (seed {}, thread seed {})",
+        best.1,
+        best.2,
+        config.seed,
+        thread_seed(config.seed, best.2)
+    );
+    let output = args
+        .output
+        .unwrap_or_else(|| format!("solution_{}", best.1));
+    if let Err(error) = write_solution(&output, &problem, &best.0.solution, args.with_ids) {
+        eprintln!("Error: {}: {}", output, error);
+        std::process::exit(1);
+    }
+    let unallocated = best.0.solution.unallocated();
+    if !unallocated.is_empty() {
+        let mut ids: Vec<_> = unallocated
+            .iter()
+            .map(|&x| problem.reservations.id[x])
+            .collect();
+        ids.sort_unstable();
+        println!(
+            "Could not place {} reservations: {}",
+            ids.len(),
+            ids.iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    println!("Done! took {} seconds", now.elapsed().as_secs_f64());
+}
+
+fn write_solution(
+    path: &str,
+    problem: &Problem,
+    solution: &Solution,
+    with_ids: bool,
+) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for (reservation, cottage) in solution.mapping().iter().enumerate() {
+        if with_ids {
+            write!(file, "{} ", problem.reservations.id[reservation])?;
+        }
+        match cottage {
+            Some(cottage) => writeln!(file, "{}", problem.cottages.id[*cottage])?,
+            None => writeln!(file, "-")?,
+        }
+    }
+    file.flush()
+}
+
+fn evaluate(args: SolutionArgs) {
+    let problem = read_problem(&args.input);
+    let evaluation = evaluate_ids(&problem, &read_solution_file(args.solution, &problem));
+    print!("{}", Report::new(&problem, &evaluation.solution));
+
+    if !evaluation.violations.is_empty() {
+        println!();
+        println!("Violations:");
+        for violation in &evaluation.violations {
+            println!("{}", violation.describe(&problem));
+        }
+    }
+}
+
+fn validate(args: SolutionArgs) {
+    let problem = read_problem(&args.input);
+    let evaluation = evaluate_ids(&problem, &read_solution_file(args.solution, &problem));
 
-        instances.push((instance, objective));
+    for violation in &evaluation.violations {
+        println!("{}", violation.describe(&problem));
     }
 
-    let best = instances.iter().min_by_key(|x| x.1).unwrap();
-    let mapping = best.0.solution.mapping();
-    let output_mapping = mapping
+    if !evaluation.violations.is_empty() {
+        println!("{} violations found", evaluation.violations.len());
+        std::process::exit(1);
+    }
+    println!("Solution is valid");
+}
+
+fn inspect(args: InputArgs) {
+    let problem = read_problem(&args);
+    let allocation_penalty = &problem.allocation_penalty;
+    let reservations = problem.reservations.reservations();
+    let fixed = problem
+        .reservations
+        .cottage_number
         .iter()
-        .map(|x| problem.cottages.id[x.expect("Mapping is always there")]);
+        .filter(|x| x.is_some())
+        .count();
+    let targets = allocation_penalty.possible_pairs();
 
-    let mut file = std::fs::File::create(format!("solution_{}", best.1))
-        .expect("Could not create solution file");
+    println!("Cottages: {}", problem.cottages.cottages());
+    println!("Reservations: {}", reservations);
+    println!("Timeslots: {}", problem.timeslots());
+    println!("Horizon: days {:?}", problem.horizon());
+    println!("Phase: {}", problem.phase);
+    let amenities: Vec<_> = problem
+        .amenities
+        .names()
+        .iter()
+        .enumerate()
+        .map(|(i, name)| match problem.amenities.is_soft(i) {
+            true => format!("{} (soft)", name),
+            false => name.clone(),
+        })
+        .collect();
+    println!("Amenities: {}", amenities.join(", "));
+    println!("Fixed reservations: {}", fixed);
+    println!(
+        "Blocked cottages: {}",
+        problem
+            .cottages
+            .blocks
+            .iter()
+            .filter(|x| !x.is_empty())
+            .count()
+    );
+    println!(
+        "Reallocatable reservations: {}",
+        allocation_penalty.reallocatable_reservations.len()
+    );
+    println!(
+        "Average possible cottages: {:.2}",
+        targets as f64 / reservations as f64
+    );
+
+    let infeasibilities = check_feasibility(&problem);
+    println!("Infeasibilities: {}", infeasibilities.len());
+    print_infeasibilities(&problem, &infeasibilities);
+}
 
-    for om in output_mapping {
-        write!(file, "{}\n", om).unwrap();
+fn main() {
+    match Cli::parse().command {
+        Command::Solve(args) => solve(args),
+        Command::Evaluate(args) => evaluate(args),
+        Command::Validate(args) => validate(args),
+        Command::Inspect(args) => inspect(args),
     }
-    println!("Done! took {} seconds", now.elapsed().as_secs_f64());
 }
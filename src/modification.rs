@@ -16,6 +16,12 @@ pub struct Chain {
     step: usize,
 }
 
+impl Default for Chain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Chain {
     pub fn new() -> Self {
         Self {
@@ -55,6 +61,10 @@ impl Chain {
         self.chain.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.chain.is_empty()
+    }
+
     pub fn clear(&mut self) {
         self.chain.clear();
         self.cost = Cost::empty();
@@ -133,21 +143,21 @@ impl Modification {
                 .get_unchecked(reservation)
                 .unwrap_unchecked()
         };
-        let range = problem.reservations.range(reservation);
+        let range = problem.occupied(reservation, cottage);
         let target = Target::new(reservation, cottage, range);
         Self::Unassign(target)
     }
 
     pub fn assign(reservation: usize, cottage: usize, problem: &Problem) -> Self {
-        let range = problem.reservations.range(reservation);
+        let range = problem.occupied(reservation, cottage);
         let target = Target::new(reservation, cottage, range);
         Self::Assign(target)
     }
 
     pub fn target(&self) -> &Target {
         match self {
-            Modification::Unassign(target) => &target,
-            Modification::Assign(target) => &target,
+            Modification::Unassign(target) => target,
+            Modification::Assign(target) => target,
         }
     }
 }
@@ -163,10 +173,9 @@ mod test {
         cottages.class = vec![0, 1];
 
         let mut reservations = Reservations::empty(2);
-        reservations.arrival = vec![0, 5];
-        reservations.stay = vec![5, 5];
+        reservations.set_arrival(vec![0, 5]);
+        reservations.set_stay(vec![5, 5]);
         reservations.class = vec![0, 0];
-        reservations.update();
 
         let problem = Problem::new(cottages, reservations, 0);
         let mut solution = Solution::naive(&problem);
@@ -180,7 +189,7 @@ mod test {
 
         for _ in 0..100 {
             chain.progress(&problem, &mut solution);
-            assert_eq!(&chain.cost, &Cost::new(-1, 1, 0, -1));
+            assert_eq!(&chain.cost, &Cost::new(-1, 1, 0, -1, 0, 0));
 
             chain.regress(&problem, &mut solution);
             assert_eq!(&chain.cost, &Cost::empty());
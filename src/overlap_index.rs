@@ -0,0 +1,111 @@
+use std::ops::Range;
+
+// Reservations per day, so overlaps are found without scanning every reservation.
+// Both lists are flat, the reservations for `day` are at `start[day]..start[day + 1]`.
+#[derive(Debug, Clone, Default)]
+pub struct OverlapIndex {
+    // Staying on the day
+    present_start: Vec<usize>,
+    present: Vec<usize>,
+    // Arriving on the day
+    arriving_start: Vec<usize>,
+    arriving: Vec<usize>,
+}
+
+impl OverlapIndex {
+    pub fn new(arrival: &[usize], departure: &[usize]) -> Self {
+        let days = departure.iter().copied().max().unwrap_or(0);
+
+        // Counts per day turned into offsets
+        let mut present_start = vec![0isize; days + 2];
+        let mut arriving_start = vec![0; days + 2];
+        for (&arrival, &departure) in arrival.iter().zip(departure.iter()) {
+            present_start[arrival + 1] += 1;
+            present_start[departure + 1] -= 1;
+            arriving_start[arrival + 1] += 1;
+        }
+        let mut staying = 0;
+        for day in 1..days + 2 {
+            staying += present_start[day];
+            present_start[day] = present_start[day - 1] + staying;
+            arriving_start[day] += arriving_start[day - 1];
+        }
+        let present_start: Vec<usize> = present_start.into_iter().map(|x| x as usize).collect();
+
+        // Filled in reservation order, so every day is sorted
+        let mut present = vec![0; present_start[days + 1]];
+        let mut arriving = vec![0; arrival.len()];
+        let mut present_next = present_start.clone();
+        let mut arriving_next = arriving_start.clone();
+        for (reservation, (&arrival, &departure)) in
+            arrival.iter().zip(departure.iter()).enumerate()
+        {
+            for day in arrival..departure {
+                present[present_next[day]] = reservation;
+                present_next[day] += 1;
+            }
+            arriving[arriving_next[arrival]] = reservation;
+            arriving_next[arrival] += 1;
+        }
+
+        Self {
+            present_start,
+            present,
+            arriving_start,
+            arriving,
+        }
+    }
+
+    fn days(&self) -> usize {
+        self.present_start.len().saturating_sub(2)
+    }
+
+    // Reservations that stay at least one day in `range`, each once:
+    // those present on its first day, then those arriving later on
+    pub fn query(&self, range: Range<usize>) -> impl Iterator<Item = usize> + '_ {
+        let start = range.start.min(self.days());
+        let end = range.end.min(self.days()).max(start);
+
+        let present = if start < end {
+            &self.present[self.present_start[start]..self.present_start[start + 1]]
+        } else {
+            &[]
+        };
+        let arriving = if start + 1 < end {
+            &self.arriving[self.arriving_start[start + 1]..self.arriving_start[end]]
+        } else {
+            &[]
+        };
+        present.iter().chain(arriving.iter()).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_overlap_index() {
+        let arrival = vec![0, 2, 1, 6, 3, 3, 0];
+        let departure = vec![4, 5, 4, 7, 3, 9, 1];
+        let index = OverlapIndex::new(&arrival, &departure);
+
+        for start in 0..12 {
+            for end in start + 1..12 {
+                let mut found: Vec<_> = index.query(start..end).collect();
+                found.sort_unstable();
+
+                let expected: Vec<_> = (0..arrival.len())
+                    .filter(|&x| arrival[x] < end && start < departure[x])
+                    .collect();
+                assert_eq!(found, expected, "{start}..{end}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_overlap_index_empty() {
+        let index = OverlapIndex::default();
+        assert_eq!(index.query(0..10).count(), 0);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{problem::Problem, solution::Solution};
+
+// Soft preferences of `reservation` that `cottage` does not meet
+fn unmet_preferences(problem: &Problem, cottage: usize, reservation: usize) -> usize {
+    problem.reservations.preference[reservation].count_missing(
+        &problem.cottages.preference[cottage],
+        problem.amenities.soft(),
+    )
+}
+
+pub fn calculate_preferences(problem: &Problem, solution: &Solution) -> usize {
+    solution
+        .mapping()
+        .iter()
+        .enumerate()
+        .filter_map(|(reservation, cottage)| {
+            cottage.map(|cottage| unmet_preferences(problem, cottage, reservation))
+        })
+        .sum()
+}
+
+pub fn calculate_preference(problem: &Problem, solution: &Solution, reservation: usize) -> usize {
+    let cottage = solution.mapping()[reservation];
+    cottage
+        .map(|cottage| unmet_preferences(problem, cottage, reservation))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        amenity::{AmenitySchema, AmenitySet},
+        problem::{Cottages, Reservations},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_soft_preference_cost() {
+        let size = 2;
+        let mut cottages = Cottages::empty(size);
+        let mut reservations = Reservations::empty(size);
+        cottages.preference = vec![[0].into_iter().collect(), AmenitySet::default()];
+        reservations.preference = vec![[0, 1].into_iter().collect(), [0, 1].into_iter().collect()];
+        reservations.set_arrival(vec![0, 1]);
+
+        let mut amenities = AmenitySchema::new(vec!["Near Lake".to_string(), "Sauna".to_string()]);
+        amenities.set_soft(0);
+        amenities.set_soft(1);
+
+        let problem = Problem::with_amenities(cottages, reservations, 0, amenities);
+        let solution = Solution::naive(&problem);
+
+        assert_eq!(calculate_preferences(&problem, &solution), 3);
+        assert_eq!(calculate_preference(&problem, &solution, 0), 1);
+        assert_eq!(calculate_preference(&problem, &solution, 1), 2);
+    }
+
+    #[test]
+    fn test_hard_preference_no_cost() {
+        let size = 1;
+        let mut cottages = Cottages::empty(size);
+        let mut reservations = Reservations::empty(size);
+        cottages.preference = vec![[0, 1].into_iter().collect()];
+        reservations.preference = vec![[0, 1].into_iter().collect()];
+
+        let amenities = AmenitySchema::new(vec!["Near Lake".to_string(), "Sauna".to_string()]);
+        let problem = Problem::with_amenities(cottages, reservations, 0, amenities);
+        let solution = Solution::naive(&problem);
+
+        assert_eq!(calculate_preferences(&problem, &solution), 0);
+    }
+}
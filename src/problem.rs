@@ -1,13 +1,22 @@
-use std::ops::Range;
+use std::{ops::Range, str::FromStr, sync::Arc};
 
-use crate::allocation_penalty::AllocationPenalty;
+use crate::{
+    allocation_penalty::AllocationPenalty,
+    amenity::{AmenitySchema, AmenitySet},
+    cost::CostWeights,
+    overlap_index::OverlapIndex,
+};
 
 #[derive(Debug, Clone)]
 pub struct Cottages {
     pub id: Vec<usize>,
     pub capacity: Vec<usize>,
     pub class: Vec<usize>,
-    pub preference: Vec<[bool; 10]>,
+    pub preference: Vec<AmenitySet>,
+    // Days on which a cottage can not be let, for maintenance or by its owner
+    pub blocks: Vec<Vec<Range<usize>>>,
+    // Free days a cottage needs after a departure before the next arrival
+    pub turnover: Vec<usize>,
 }
 
 impl Cottages {
@@ -16,7 +25,9 @@ impl Cottages {
             id: vec![0; size],
             capacity: vec![0; size],
             class: vec![0; size],
-            preference: vec![[false; 10]; size],
+            preference: vec![AmenitySet::default(); size],
+            blocks: vec![Vec::new(); size],
+            turnover: vec![0; size],
         }
     }
 
@@ -24,17 +35,27 @@ impl Cottages {
         self.id.len()
     }
 
+    pub fn is_blocked(&self, cottage: usize, range: Range<usize>) -> bool {
+        self.blocks[cottage]
+            .iter()
+            .any(|block| block.start < range.end && range.start < block.end)
+    }
+
     pub fn new(
         id: Vec<usize>,
         capacity: Vec<usize>,
         class: Vec<usize>,
-        preference: Vec<[bool; 10]>,
+        preference: Vec<AmenitySet>,
     ) -> Self {
+        let blocks = vec![Vec::new(); id.len()];
+        let turnover = vec![0; id.len()];
         Self {
             id,
             capacity,
             class,
             preference,
+            blocks,
+            turnover,
         }
     }
 }
@@ -44,6 +65,7 @@ impl Reservations {
         let arrival = vec![0; size];
         let stay = vec![1; size];
         let departure = Self::calculate_departure(&arrival, &stay);
+        let overlap_index = OverlapIndex::new(&arrival, &departure);
         Self {
             id: vec![0; size],
             arrival,
@@ -51,8 +73,9 @@ impl Reservations {
             departure,
             people: vec![0; size],
             class: vec![0; size],
-            preference: vec![[false; 10]; size],
+            preference: vec![AmenitySet::default(); size],
             cottage_number: vec![None; size],
+            overlap_index,
         }
     }
 
@@ -62,10 +85,11 @@ impl Reservations {
         stay: Vec<usize>,
         people: Vec<usize>,
         class: Vec<usize>,
-        preference: Vec<[bool; 10]>,
+        preference: Vec<AmenitySet>,
         cottage_number: Vec<Option<usize>>,
     ) -> Self {
         let departure = Self::calculate_departure(&arrival, &stay);
+        let overlap_index = OverlapIndex::new(&arrival, &departure);
         Self {
             id,
             arrival,
@@ -75,6 +99,7 @@ impl Reservations {
             class,
             preference,
             cottage_number,
+            overlap_index,
         }
     }
 
@@ -89,11 +114,35 @@ impl Reservations {
         assert_eq!(self.preference.len(), self.cottage_number.len());
     }
 
-    pub fn update(&mut self) {
+    pub fn arrival(&self) -> &[usize] {
+        &self.arrival
+    }
+
+    pub fn stay(&self) -> &[usize] {
+        &self.stay
+    }
+
+    pub fn departure(&self) -> &[usize] {
+        &self.departure
+    }
+
+    // Departures and the overlap index follow arrival and stay
+    pub fn set_arrival(&mut self, arrival: Vec<usize>) {
+        self.arrival = arrival;
+        self.update();
+    }
+
+    pub fn set_stay(&mut self, stay: Vec<usize>) {
+        self.stay = stay;
+        self.update();
+    }
+
+    fn update(&mut self) {
         self.departure = Self::calculate_departure(&self.arrival, &self.stay);
+        self.overlap_index = OverlapIndex::new(&self.arrival, &self.departure);
     }
 
-    pub fn calculate_departure(arrival: &Vec<usize>, stay: &Vec<usize>) -> Vec<usize> {
+    pub fn calculate_departure(arrival: &[usize], stay: &[usize]) -> Vec<usize> {
         arrival
             .iter()
             .zip(stay.iter())
@@ -111,22 +160,18 @@ impl Reservations {
             .zip(self.stay.iter())
             .map(|(arrival, stay)| arrival + stay)
             .max()
-            .unwrap()
+            .unwrap_or(0)
     }
 
     pub fn get_overlaps(&self, reservation: usize) -> impl Iterator<Item = usize> + '_ {
-        let target_arrival = self.arrival[reservation];
-        let target_departure = self.departure[reservation];
+        self.get_overlaps_range(self.range(reservation))
+            .filter(move |&i| i != reservation)
+    }
 
-        self.arrival
-            .iter()
-            .zip(self.departure.iter())
-            .enumerate()
-            .filter(move |(i, _)| *i != reservation)
-            .filter(move |(_, (arrival, departure))| {
-                **arrival < target_departure && target_arrival < **departure
-            })
-            .map(|(i, _)| i)
+    // Reservations that stay at least one day in `range`
+    // Not in any particular order, callers that need one sort the results
+    pub fn get_overlaps_range(&self, range: Range<usize>) -> impl Iterator<Item = usize> + '_ {
+        self.overlap_index.query(range)
     }
 
     pub fn range(&self, reservation: usize) -> Range<usize> {
@@ -145,13 +190,42 @@ impl Reservations {
 #[derive(Debug, Clone)]
 pub struct Reservations {
     pub id: Vec<usize>,
-    pub arrival: Vec<usize>,
-    pub stay: Vec<usize>,
-    pub departure: Vec<usize>,
+    // Private, so that the overlap index is rebuilt whenever they change
+    arrival: Vec<usize>,
+    stay: Vec<usize>,
+    departure: Vec<usize>,
     pub people: Vec<usize>,
     pub class: Vec<usize>,
-    pub preference: Vec<[bool; 10]>,
+    pub preference: Vec<AmenitySet>,
     pub cottage_number: Vec<Option<usize>>,
+    // Built from arrival and departure
+    overlap_index: OverlapIndex,
+}
+
+// How gaps that touch the start or end of the planning horizon are costed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundaryPolicy {
+    // Like any other gap, with the length inside the horizon
+    Count,
+    // Not at all
+    Ignore,
+    // As a gap that continues past the horizon, so it is always a legionella gap
+    OpenEnded,
+}
+
+impl FromStr for BoundaryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Self::Count),
+            "ignore" => Ok(Self::Ignore),
+            "open-ended" => Ok(Self::OpenEnded),
+            _ => Err(format!(
+                "unknown boundary policy `{s}`, expected one of: count, ignore, open-ended"
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -162,7 +236,21 @@ pub struct Problem {
     // 0: first day is friday
     // 1: first day is thursday
     pub phase: usize,
-    pub allocation_penalty: AllocationPenalty,
+    // Read-only after construction, shared by the clones each thread works on
+    pub allocation_penalty: Arc<AllocationPenalty>,
+    pub weights: CostWeights,
+    // Amenities that preferences refer to
+    pub amenities: AmenitySchema,
+    // Days on which gaps are costed. Stays may extend past it.
+    // None: from day 0 to the last departure
+    pub horizon: Option<Range<usize>>,
+    pub boundary: BoundaryPolicy,
+    // Reservations may be left without a cottage, each costs `weights.unallocated`
+    pub allow_unallocated: bool,
+    // IDs of the reservations that were read but left out, their stay misses the horizon
+    pub skipped: Vec<usize>,
+    // Input columns that are neither a field nor an amenity
+    pub ignored_columns: Vec<String>,
 }
 
 impl Problem {
@@ -173,14 +261,68 @@ impl Problem {
     }
 
     pub fn new(cottages: Cottages, reservations: Reservations, phase: usize) -> Self {
-        let allocation_penalty = AllocationPenalty::calculate(&cottages, &reservations);
+        Self::with_amenities(cottages, reservations, phase, AmenitySchema::default())
+    }
+
+    // The schema decides which preferences are hard, so it is needed up front
+    pub fn with_amenities(
+        cottages: Cottages,
+        reservations: Reservations,
+        phase: usize,
+        amenities: AmenitySchema,
+    ) -> Self {
+        let allocation_penalty = Arc::new(AllocationPenalty::calculate(
+            &cottages,
+            &reservations,
+            amenities.soft(),
+        ));
         Self {
             cottages,
             reservations,
             phase,
             allocation_penalty,
+            weights: CostWeights::default(),
+            amenities,
+            horizon: None,
+            boundary: BoundaryPolicy::Count,
+            allow_unallocated: false,
+            skipped: Vec::new(),
+            ignored_columns: Vec::new(),
         }
     }
+
+    pub fn horizon(&self) -> Range<usize> {
+        self.horizon
+            .clone()
+            .unwrap_or(0..self.reservations.timeslots())
+    }
+
+    // Length of the timetables, covering the horizon and every full stay
+    // with its turnover days
+    pub fn timeslots(&self) -> usize {
+        let turnover = self.cottages.turnover.iter().max().copied().unwrap_or(0);
+        (self.reservations.timeslots() + turnover).max(self.horizon().end)
+    }
+
+    // Days a reservation takes up in a cottage: the stay and the turnover after it
+    pub fn occupied(&self, reservation: usize, cottage: usize) -> Range<usize> {
+        Self::occupied_in(&self.cottages, &self.reservations, reservation, cottage)
+    }
+
+    // Like `occupied`, for use while the problem is being built
+    pub fn occupied_in(
+        cottages: &Cottages,
+        reservations: &Reservations,
+        reservation: usize,
+        cottage: usize,
+    ) -> Range<usize> {
+        #[cfg(feature = "supersafe")]
+        assert!(cottages.turnover.get(cottage).is_some());
+
+        let range = reservations.range(reservation);
+        let turnover = unsafe { *cottages.turnover.get_unchecked(cottage) };
+        range.start..range.end + turnover
+    }
 }
 
 #[cfg(test)]
@@ -190,8 +332,8 @@ mod test {
     fn timeslots_minimal() {
         let size = 1;
         let mut problem = Problem::empty(size, size);
-        problem.reservations.arrival = vec![0];
-        problem.reservations.stay = vec![1];
+        problem.reservations.set_arrival(vec![0]);
+        problem.reservations.set_stay(vec![1]);
 
         let timeslots = problem.reservations.timeslots();
         assert_eq!(timeslots, 1);
@@ -201,10 +343,71 @@ mod test {
     fn timeslots_mixed() {
         let size = 3;
         let mut problem = Problem::empty(size, size);
-        problem.reservations.arrival = vec![0, 1];
-        problem.reservations.stay = vec![2, 2];
+        problem.reservations.set_arrival(vec![0, 1]);
+        problem.reservations.set_stay(vec![2, 2]);
 
         let timeslots = problem.reservations.timeslots();
         assert_eq!(timeslots, 3);
     }
+
+    #[test]
+    fn timeslots_horizon() {
+        let mut problem = Problem::empty(1, 2);
+        problem.reservations.set_arrival(vec![0, 3]);
+        problem.reservations.set_stay(vec![2, 4]);
+        assert_eq!(problem.horizon(), 0..7);
+
+        // The last stay runs past the horizon
+        problem.horizon = Some(1..5);
+        assert_eq!(problem.horizon(), 1..5);
+        assert_eq!(problem.timeslots(), 7);
+
+        problem.horizon = Some(0..9);
+        assert_eq!(problem.timeslots(), 9);
+    }
+
+    #[test]
+    fn cottage_blocked() {
+        let mut cottages = Cottages::empty(2);
+        cottages.blocks[0] = vec![3..5, 8..9];
+
+        assert!(cottages.is_blocked(0, 4..6));
+        assert!(cottages.is_blocked(0, 0..10));
+        assert!(!cottages.is_blocked(0, 5..8));
+        assert!(!cottages.is_blocked(0, 0..3));
+        assert!(!cottages.is_blocked(1, 0..10));
+    }
+
+    #[test]
+    fn occupied_turnover() {
+        let mut problem = Problem::empty(2, 1);
+        problem.reservations.set_arrival(vec![2]);
+        problem.reservations.set_stay(vec![3]);
+        problem.cottages.turnover = vec![0, 2];
+
+        assert_eq!(problem.occupied(0, 0), 2..5);
+        assert_eq!(problem.occupied(0, 1), 2..7);
+        assert_eq!(problem.timeslots(), 7);
+    }
+
+    #[test]
+    fn overlaps_follow_stays() {
+        let mut reservations = Reservations::empty(3);
+        reservations.set_arrival(vec![0, 2, 4]);
+        reservations.set_stay(vec![3, 2, 1]);
+
+        let mut overlaps: Vec<_> = reservations.get_overlaps(1).collect();
+        overlaps.sort_unstable();
+        assert_eq!(overlaps, vec![0]);
+        assert_eq!(reservations.departure(), &[3, 4, 5]);
+
+        reservations.set_stay(vec![1, 2, 1]);
+        assert_eq!(reservations.get_overlaps(1).count(), 0);
+    }
+
+    #[test]
+    fn parse_boundary_policy() {
+        assert_eq!("open-ended".parse(), Ok(BoundaryPolicy::OpenEnded));
+        assert!("open".parse::<BoundaryPolicy>().is_err());
+    }
 }
@@ -1,8 +1,340 @@
-use std::fs::read_to_string;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    fs::read_to_string,
+    io,
+    ops::Range,
+};
 
-use crate::problem::{Cottages, Problem, Reservations};
+use calamine::{open_workbook_auto, Data, Reader};
+
+use crate::{
+    amenity::{AmenitySchema, AmenitySet},
+    cost::CostWeights,
+    problem::{Cottages, Problem, Reservations},
+};
 use chrono::prelude::*;
-use serde::{Deserialize, Serialize};
+use chrono_tz::Tz;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    // The file as a whole is not valid JSON of the expected shape
+    Json {
+        path: String,
+        source: serde_json::Error,
+    },
+    Csv {
+        path: String,
+        source: csv::Error,
+    },
+    // The workbook can not be opened or lacks a sheet
+    Workbook {
+        path: String,
+        source: calamine::Error,
+    },
+    // A single record (or solution line) is invalid.
+    // `id` is the cottage or reservation ID, if the record has a readable one.
+    Record {
+        path: String,
+        record: usize,
+        id: Option<usize>,
+        field: Option<String>,
+        message: String,
+    },
+    // An amenity named in the options is not a cottage column
+    UnknownAmenity {
+        path: String,
+        name: String,
+    },
+    // A solution without reservation IDs has a line per reservation,
+    // any other count can not be matched to the reservations
+    LineCount {
+        path: String,
+        expected: usize,
+        found: usize,
+    },
+    // The planning horizon in the options ends before it starts
+    Horizon {
+        start: NaiveDate,
+        end: NaiveDate,
+    },
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io { path, source } => write!(f, "{}: {}", path, source),
+            ReadError::Json { path, source } => write!(f, "{}: {}", path, source),
+            ReadError::Csv { path, source } => write!(f, "{}: {}", path, source),
+            ReadError::Workbook { path, source } => write!(f, "{}: {}", path, source),
+            ReadError::Record {
+                path,
+                record,
+                id,
+                field,
+                message,
+            } => {
+                write!(f, "{}: record {}", path, record)?;
+                if let Some(id) = id {
+                    write!(f, " (ID {})", id)?;
+                }
+                if let Some(field) = field {
+                    write!(f, ", field `{}`", field)?;
+                }
+                write!(f, ": {}", message)
+            }
+            ReadError::UnknownAmenity { path, name } => {
+                write!(f, "{}: no amenity column `{}`", path, name)
+            }
+            ReadError::LineCount {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: {} lines for {} reservations, \
+                 write the solution with reservation IDs to reuse it on changed reservations",
+                path, found, expected
+            ),
+            ReadError::Horizon { start, end } => write!(
+                f,
+                "the planning horizon ends on {} before it starts on {}",
+                end, start
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io { source, .. } => Some(source),
+            ReadError::Json { source, .. } => Some(source),
+            ReadError::Csv { source, .. } => Some(source),
+            ReadError::Workbook { source, .. } => Some(source),
+            ReadError::Record { .. } => None,
+            ReadError::UnknownAmenity { .. } => None,
+            ReadError::LineCount { .. } => None,
+            ReadError::Horizon { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    // Days start at midnight in this timezone
+    pub timezone: Tz,
+    // First day of the planning horizon.
+    // None: the day of the earliest arrival
+    pub horizon_start: Option<NaiveDate>,
+    // First day after the planning horizon.
+    // None: the day after the latest arrival
+    pub horizon_end: Option<NaiveDate>,
+    // Amenity columns, in the order of their bits.
+    // None: every cottage column that is not a field and only holds 0 or 1, in header order.
+    // Columns that are neither a field nor an amenity are ignored.
+    pub amenities: Option<Vec<String>>,
+    // Amenities that are wishes, all others must be met
+    pub soft_amenities: Vec<String>,
+    // Table of cottage blocks, JSON or CSV.
+    // Workbooks also read a `Blocks` sheet if there is one.
+    pub blocks: Option<String>,
+    // Turnover days per cottage class, classes not listed need none
+    pub turnover: HashMap<usize, usize>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            timezone: Tz::UTC,
+            horizon_start: None,
+            horizon_end: None,
+            amenities: None,
+            soft_amenities: Vec::new(),
+            blocks: None,
+            turnover: HashMap::new(),
+        }
+    }
+}
+
+fn read_file(path: &str) -> Result<String, ReadError> {
+    read_to_string(path).map_err(|source| ReadError::Io {
+        path: path.to_string(),
+        source,
+    })
+}
+
+// The ID of a raw record, used to point at the record in errors
+fn record_id(record: &serde_json::Value) -> Option<usize> {
+    record
+        .get("ID")
+        .or_else(|| record.get("id"))
+        .and_then(|x| x.as_u64())
+        .map(|x| x as usize)
+}
+
+// Whole numbers become integers, spreadsheets store every number as a float
+fn number_value(x: f64) -> serde_json::Value {
+    if x.fract() == 0f64 && x.abs() < (1u64 << 53) as f64 {
+        (x as i64).into()
+    } else {
+        x.into()
+    }
+}
+
+fn text_value(text: &str) -> serde_json::Value {
+    match (text.parse::<i64>(), text.parse::<f64>()) {
+        (Ok(x), _) => x.into(),
+        (_, Ok(x)) => number_value(x),
+        _ => text.into(),
+    }
+}
+
+// Empty cells are left out, so they read as missing fields
+fn cell_value(cell: &Data) -> Option<serde_json::Value> {
+    match cell {
+        Data::Int(x) => Some((*x).into()),
+        Data::Float(x) => Some(number_value(*x)),
+        Data::String(x) => Some(x.as_str().into()),
+        Data::Bool(x) => Some((*x as i64).into()),
+        Data::DateTime(x) => x
+            .as_datetime()
+            .map(|x| x.format("%Y-%m-%dT%H:%M:%S").to_string().into()),
+        Data::DateTimeIso(x) | Data::DurationIso(x) => Some(x.as_str().into()),
+        Data::Error(_) | Data::Empty => None,
+    }
+}
+
+// Raw records of one input table, keyed by column header like the JSON input.
+// `path` names the file, and the sheet for workbooks, in errors.
+struct Table {
+    path: String,
+    records: Vec<serde_json::Value>,
+}
+
+impl Table {
+    fn json(path: &str) -> Result<Self, ReadError> {
+        let json_string = read_file(path)?;
+        let records = serde_json::from_str(&json_string).map_err(|source| ReadError::Json {
+            path: path.to_string(),
+            source,
+        })?;
+
+        Ok(Self {
+            path: path.to_string(),
+            records,
+        })
+    }
+
+    fn csv(path: &str) -> Result<Self, ReadError> {
+        let csv_error = |source| ReadError::Csv {
+            path: path.to_string(),
+            source,
+        };
+        let mut reader = csv::Reader::from_path(path).map_err(csv_error)?;
+        let headers = reader.headers().map_err(csv_error)?.clone();
+
+        let records = reader
+            .records()
+            .map(|row| {
+                let row = row.map_err(csv_error)?;
+                let record = headers
+                    .iter()
+                    .zip(row.iter())
+                    .filter(|(_, field)| !field.is_empty())
+                    .map(|(header, field)| (header.to_string(), text_value(field)))
+                    .collect();
+                Ok(serde_json::Value::Object(record))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            path: path.to_string(),
+            records,
+        })
+    }
+
+    fn xlsx(path: &str, sheet: &str) -> Result<Self, ReadError> {
+        let workbook_error = |source| ReadError::Workbook {
+            path: path.to_string(),
+            source,
+        };
+        let mut workbook = open_workbook_auto(path).map_err(workbook_error)?;
+        let range = workbook.worksheet_range(sheet).map_err(workbook_error)?;
+
+        Ok(Self::cells(format!("{} ({})", path, sheet), range.rows()))
+    }
+
+    fn has_sheet(path: &str, sheet: &str) -> Result<bool, ReadError> {
+        let workbook = open_workbook_auto(path).map_err(|source| ReadError::Workbook {
+            path: path.to_string(),
+            source,
+        })?;
+        Ok(workbook.sheet_names().iter().any(|x| x == sheet))
+    }
+
+    // The first row holds the headers, empty rows are skipped
+    fn cells<'a>(path: String, mut rows: impl Iterator<Item = &'a [Data]>) -> Self {
+        let headers: Vec<String> = rows
+            .next()
+            .map(|row| row.iter().map(|x| x.to_string()).collect())
+            .unwrap_or_default();
+
+        let records = rows
+            .filter(|row| row.iter().any(|x| *x != Data::Empty))
+            .map(|row| {
+                let record = headers
+                    .iter()
+                    .zip(row.iter())
+                    .filter_map(|(header, cell)| Some((header.clone(), cell_value(cell)?)))
+                    .collect();
+                serde_json::Value::Object(record)
+            })
+            .collect();
+
+        Self { path, records }
+    }
+
+    // Column headers in the order they first appear
+    fn headers(&self) -> Vec<&str> {
+        let mut headers: Vec<&str> = Vec::new();
+        for record in self.records.iter().filter_map(|x| x.as_object()) {
+            for header in record.keys() {
+                if !headers.contains(&header.as_str()) {
+                    headers.push(header);
+                }
+            }
+        }
+        headers
+    }
+
+    // Parse one record at a time,
+    // so that an error can name the record and the field it is in
+    fn parse<T: DeserializeOwned>(&self) -> Result<Vec<T>, ReadError> {
+        self.records
+            .iter()
+            .enumerate()
+            .map(|(record, value)| {
+                let id = record_id(value);
+                serde_path_to_error::deserialize(value).map_err(|error| {
+                    let field = error.path().to_string();
+                    ReadError::Record {
+                        path: self.path.clone(),
+                        record,
+                        id,
+                        field: (field != ".").then_some(field),
+                        message: error.into_inner().to_string(),
+                    }
+                })
+            })
+            .collect()
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CottageItem {
@@ -15,35 +347,50 @@ struct CottageItem {
     #[serde(alias = "Class")]
     class: usize,
 
-    #[serde(alias = "Face South")]
-    face_south: usize,
-
-    #[serde(alias = "Near Playground")]
-    near_playground: usize,
-
-    #[serde(alias = "Close to the Centre")]
-    close_to_the_center: usize,
-
-    #[serde(alias = "Near Lake ")]
-    near_lake: usize,
-
-    #[serde(alias = "Near car park")]
-    near_car_park: usize,
+    // Every other column, the amenities among them are 0 or 1
+    #[serde(flatten)]
+    columns: BTreeMap<String, serde_json::Value>,
+}
 
-    #[serde(alias = "Accessible for Wheelchair")]
-    accessible_for_wheelchairs: usize,
+// A cottage is blocked from the first to the last day, inclusive
+#[derive(Debug, Serialize, Deserialize)]
+struct BlockItem {
+    // Cottage ID
+    #[serde(alias = "Cottage")]
+    cottage: usize,
 
-    #[serde(alias = "Child Friendly")]
-    child_friendly: usize,
+    #[serde(alias = "First Day")]
+    first: ArrivalDate,
 
-    #[serde(alias = "Dish Washer ")]
-    dish_washer: usize,
+    #[serde(alias = "Last Day")]
+    last: ArrivalDate,
+}
 
-    #[serde(alias = "Wi-Fi Coverage ")]
-    wifi_coverage: usize,
+// Epoch milliseconds, or an ISO-8601 date or date-time
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum ArrivalDate {
+    Millis(i64),
+    Text(String),
+}
 
-    #[serde(alias = "Covered Terrace")]
-    covered_terrace: usize,
+impl ArrivalDate {
+    // The local date in `timezone`.
+    // Date-times without an offset are taken as local already.
+    fn date(&self, timezone: Tz) -> Result<NaiveDate, String> {
+        match self {
+            ArrivalDate::Millis(millis) => Utc
+                .timestamp_millis_opt(*millis)
+                .single()
+                .map(|x| x.with_timezone(&timezone).date_naive())
+                .ok_or_else(|| format!("timestamp {} is out of range", millis)),
+            ArrivalDate::Text(text) => DateTime::parse_from_rfc3339(text)
+                .map(|x| x.with_timezone(&timezone).date_naive())
+                .or_else(|_| text.parse::<NaiveDateTime>().map(|x| x.date()))
+                .or_else(|_| text.parse::<NaiveDate>())
+                .map_err(|_| format!("`{}` is not an ISO-8601 date", text)),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,7 +399,7 @@ struct ReservationItem {
     id: usize,
 
     #[serde(alias = "Arrival Date")]
-    arrival: i64,
+    arrival: ArrivalDate,
 
     #[serde(alias = "Length of Stay")]
     stay: usize,
@@ -63,120 +410,246 @@ struct ReservationItem {
     #[serde(alias = "Class")]
     class: usize,
 
-    #[serde(alias = "Face South")]
-    face_south: usize,
-
-    #[serde(alias = "Near Playground")]
-    near_playground: usize,
-
-    #[serde(alias = "Close to the Centre")]
-    close_to_the_center: usize,
-
-    #[serde(alias = "Near Lake ")]
-    near_lake: usize,
-
-    #[serde(alias = "Near car park")]
-    near_car_park: usize,
-
-    #[serde(alias = "Accessible for Wheelchair")]
-    accessible_for_wheelchairs: usize,
-
-    #[serde(alias = "Child Friendly")]
-    child_friendly: usize,
+    #[serde(alias = "Cottage (Fixed)")]
+    cottage_number: usize,
 
-    #[serde(alias = "Dish Washer ")]
-    dish_washer: usize,
+    // Every other column, the requested amenities among them are 0 or 1
+    #[serde(flatten)]
+    columns: BTreeMap<String, serde_json::Value>,
+}
 
-    #[serde(alias = "Wi-Fi Coverage ")]
-    wifi_coverage: usize,
+// The amenities set in a record's columns.
+// Header names are trimmed, the dataset has trailing spaces in some.
+fn amenity_set(
+    table: &Table,
+    record: usize,
+    id: usize,
+    columns: &BTreeMap<String, serde_json::Value>,
+    amenities: &AmenitySchema,
+) -> Result<AmenitySet, ReadError> {
+    let mut set = AmenitySet::new(amenities.len());
+    for (name, value) in columns {
+        let Some(amenity) = amenities.index(name.trim()) else {
+            continue;
+        };
+        match value.as_u64() {
+            Some(0) => {}
+            Some(_) => set.insert(amenity),
+            None => {
+                return Err(ReadError::Record {
+                    path: table.path.clone(),
+                    record,
+                    id: Some(id),
+                    field: Some(name.clone()),
+                    message: format!("expected 0 or 1, found {}", value),
+                })
+            }
+        }
+    }
+    Ok(set)
+}
 
-    #[serde(alias = "Covered Terrace")]
-    covered_terrace: usize,
+// Columns that are neither a field nor an amenity, trimmed
+fn ignored_columns<'a>(
+    columns: impl Iterator<Item = &'a BTreeMap<String, serde_json::Value>>,
+    amenities: &AmenitySchema,
+) -> BTreeSet<String> {
+    columns
+        .flat_map(|x| x.keys())
+        .map(|x| x.trim())
+        .filter(|x| amenities.index(x).is_none())
+        .map(|x| x.to_string())
+        .collect()
+}
 
-    #[serde(alias = "Cottage (Fixed)")]
-    cottage_number: usize,
+// The amenity schema is `options.amenities`, each must be a column of the cottages,
+// or read from the header if there is no list.
+// Also returns the columns that were ignored.
+pub fn read_cottages_json(
+    path: String,
+    options: &ReadOptions,
+) -> Result<(AmenitySchema, Cottages, BTreeSet<String>), ReadError> {
+    cottages_from_table(&Table::json(&path)?, options)
 }
 
-pub fn read_cottages_json(path: String) -> Cottages {
-    let json_string = read_to_string(path).expect("File not found");
-    let json: Vec<CottageItem> = serde_json::from_str(&json_string).unwrap();
+fn cottages_from_table(
+    table: &Table,
+    options: &ReadOptions,
+) -> Result<(AmenitySchema, Cottages, BTreeSet<String>), ReadError> {
+    let json: Vec<CottageItem> = table.parse()?;
+
+    let amenities = match &options.amenities {
+        Some(names) => AmenitySchema::new(names.iter().map(|x| x.trim().to_string()).collect()),
+        None => header_amenities(table, &json),
+    };
+    if !json.is_empty() {
+        for name in amenities.names() {
+            if !json
+                .iter()
+                .any(|x| x.columns.keys().any(|column| column.trim() == name))
+            {
+                return Err(ReadError::UnknownAmenity {
+                    path: table.path.clone(),
+                    name: name.clone(),
+                });
+            }
+        }
+    }
 
     let id = json.iter().map(|x| x.id).collect();
     let capacity = json.iter().map(|x| x.capacity).collect();
     let class = json.iter().map(|x| x.class).collect();
     let preference = json
         .iter()
-        .map(|x| {
-            [
-                x.face_south == 1,
-                x.near_playground == 1,
-                x.close_to_the_center == 1,
-                x.near_lake == 1,
-                x.near_car_park == 1,
-                x.accessible_for_wheelchairs == 1,
-                x.child_friendly == 1,
-                x.dish_washer == 1,
-                x.wifi_coverage == 1,
-                x.covered_terrace == 1,
-            ]
-        })
-        .collect();
+        .enumerate()
+        .map(|(record, x)| amenity_set(table, record, x.id, &x.columns, &amenities))
+        .collect::<Result<_, _>>()?;
+    let ignored = ignored_columns(json.iter().map(|x| &x.columns), &amenities);
 
-    Cottages::new(id, capacity, class, preference)
+    Ok((
+        amenities,
+        Cottages::new(id, capacity, class, preference),
+        ignored,
+    ))
 }
 
-// TODO: Fix this
-fn map_people(i: usize) -> usize {
-    match i {
-        1 => 2,
-        2 => 2,
-        3 => 4,
-        4 => 4,
-        5 => 5,
-        6 => 6,
-        7 => 8,
-        8 => 8,
-        9 => 12,
-        10 => 12,
-        11 => 12,
-        12 => 12,
-        _ => todo!(),
+// Columns of the cottages that are not a field and only hold 0 or 1, in header order.
+// Others, such as a price or a note, are not amenities.
+fn header_amenities(table: &Table, json: &[CottageItem]) -> AmenitySchema {
+    let mut names: Vec<String> = Vec::new();
+    for header in table.headers() {
+        let values: Vec<_> = json.iter().filter_map(|x| x.columns.get(header)).collect();
+        let name = header.trim();
+        if !values.is_empty()
+            && values.iter().all(|x| matches!(x.as_u64(), Some(0 | 1)))
+            && !names.iter().any(|x| x == name)
+        {
+            names.push(name.to_string());
+        }
     }
+    AmenitySchema::new(names)
 }
 
-pub fn read_reservations_json(path: String) -> (usize, Reservations) {
-    let json_string = read_to_string(path).expect("File not found");
-    let json: Vec<ReservationItem> = serde_json::from_str(&json_string).unwrap();
+// Round a party size up to the smallest cottage capacity that fits it,
+// `capacities` is sorted and deduplicated
+fn round_people(capacities: &[usize], people: usize) -> Option<usize> {
+    let index = capacities.partition_point(|&capacity| capacity < people);
+    capacities.get(index).copied()
+}
 
-    let datetime_0 = Utc.timestamp_millis(json[0].arrival);
-    let phase = (datetime_0.weekday() as usize + 7 - 4).rem_euclid(7);
+// The reservations of a table and what was left out of them.
+// Day 0 is the earliest arrival, or the horizon start if that is earlier.
+#[derive(Debug)]
+pub struct ReadReservations {
+    pub date_0: NaiveDate,
+    // The planning horizon in days
+    pub horizon: Range<usize>,
+    pub reservations: Reservations,
+    // IDs of the reservations whose stay misses the horizon
+    pub skipped: Vec<usize>,
+    // Columns that are neither a field nor an amenity
+    pub ignored: BTreeSet<String>,
+}
 
-    let id = json.iter().map(|x| x.id).collect();
-    let arrival: Vec<usize> = json
+impl ReadReservations {
+    pub fn phase(&self) -> usize {
+        phase(self.date_0)
+    }
+}
+
+// Requested amenities are the columns in `amenities`
+pub fn read_reservations_json(
+    path: String,
+    amenities: &AmenitySchema,
+    options: &ReadOptions,
+) -> Result<ReadReservations, ReadError> {
+    reservations_from_table(&Table::json(&path)?, amenities, options)
+}
+
+// How many days day 0 is away from friday
+fn phase(date_0: NaiveDate) -> usize {
+    (date_0.weekday() as usize + 7 - 4).rem_euclid(7)
+}
+
+fn reservations_from_table(
+    table: &Table,
+    amenities: &AmenitySchema,
+    options: &ReadOptions,
+) -> Result<ReadReservations, ReadError> {
+    if let (Some(start), Some(end)) = (options.horizon_start, options.horizon_end) {
+        if end < start {
+            return Err(ReadError::Horizon { start, end });
+        }
+    }
+    let json: Vec<ReservationItem> = table.parse()?;
+    let ignored = ignored_columns(json.iter().map(|x| &x.columns), amenities);
+
+    let arrival_dates = json
         .iter()
-        .map(|x| (Utc.timestamp_millis(x.arrival) - datetime_0).num_days() as usize)
-        .collect();
+        .enumerate()
+        .map(|(record, x)| {
+            x.arrival
+                .date(options.timezone)
+                .map_err(|message| ReadError::Record {
+                    path: table.path.clone(),
+                    record,
+                    id: Some(x.id),
+                    field: Some("Arrival Date".to_string()),
+                    message,
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
-    let mut stay: Vec<usize> = json.iter().map(|x| x.stay).collect();
-    let people = json.iter().map(|x| map_people(x.people)).collect();
-    let class = json.iter().map(|x| x.class).collect();
-    let preference = json
+    // Stays that run past the horizon are kept,
+    // stays that miss it entirely do not belong to the problem
+    let (kept, skipped): (Vec<usize>, Vec<usize>) = (0..json.len()).partition(|&record| {
+        let departure = arrival_dates[record] + chrono::Duration::days(json[record].stay as i64);
+        options.horizon_start.is_none_or(|x| departure > x)
+            && options
+                .horizon_end
+                .is_none_or(|x| arrival_dates[record] < x)
+    });
+    let skipped = skipped.iter().map(|&record| json[record].id).collect();
+
+    let preference = kept
         .iter()
-        .map(|x| {
-            [
-                x.face_south == 1,
-                x.near_playground == 1,
-                x.close_to_the_center == 1,
-                x.near_lake == 1,
-                x.near_car_park == 1,
-                x.accessible_for_wheelchairs == 1,
-                x.child_friendly == 1,
-                x.dish_washer == 1,
-                x.wifi_coverage == 1,
-                x.covered_terrace == 1,
-            ]
+        .map(|&record| {
+            let x = &json[record];
+            amenity_set(table, record, x.id, &x.columns, amenities)
         })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let Some(earliest_arrival) = kept.iter().map(|&record| arrival_dates[record]).min() else {
+        let date_0 = options.horizon_start.unwrap_or_default();
+        return Ok(ReadReservations {
+            date_0,
+            horizon: 0..0,
+            reservations: Reservations::empty(0),
+            skipped,
+            ignored,
+        });
+    };
+    let date_0 = options
+        .horizon_start
+        .map_or(earliest_arrival, |x| x.min(earliest_arrival));
+    let day = |x: NaiveDate| (x - date_0).num_days() as usize;
+
+    let json: Vec<_> = kept.iter().map(|&record| &json[record]).collect();
+    let id = json.iter().map(|x| x.id).collect();
+    let arrival: Vec<usize> = kept
+        .iter()
+        .map(|&record| day(arrival_dates[record]))
         .collect();
+    let stay: Vec<usize> = json.iter().map(|x| x.stay).collect();
+
+    let start = options.horizon_start.map_or(0, day);
+    let end = match options.horizon_end {
+        Some(x) => day(x.max(date_0)),
+        None => arrival.iter().max().map_or(0, |x| x + 1),
+    };
+
+    let people = json.iter().map(|x| x.people).collect();
+    let class = json.iter().map(|x| x.class).collect();
     let cottage_number = json
         .iter()
         .map(|x| match x.cottage_number {
@@ -185,23 +658,879 @@ pub fn read_reservations_json(path: String) -> (usize, Reservations) {
         })
         .collect();
 
-    // TODO: Fix!
-    let latest_arrival = arrival.iter().max().unwrap();
-    for reservation in 0..arrival.len() {
-        if arrival[reservation] + stay[reservation] > *latest_arrival {
-            stay[reservation] = latest_arrival - arrival[reservation] + 1;
+    Ok(ReadReservations {
+        date_0,
+        horizon: start..end,
+        reservations: Reservations::new(
+            id,
+            arrival,
+            stay,
+            people,
+            class,
+            preference,
+            cottage_number,
+        ),
+        skipped,
+        ignored,
+    })
+}
+
+// Adds the blocks to `cottages`, in days from `date_0`.
+// Blocks that end before day 0 are dropped.
+fn blocks_from_table(
+    table: &Table,
+    cottages: &mut Cottages,
+    date_0: NaiveDate,
+    options: &ReadOptions,
+) -> Result<(), ReadError> {
+    let json: Vec<BlockItem> = table.parse()?;
+    let cottage_index: HashMap<_, _> = cottages
+        .id
+        .iter()
+        .enumerate()
+        .map(|(index, &id)| (id, index))
+        .collect();
+
+    for (record, x) in json.iter().enumerate() {
+        let error = |field: &str, message: String| ReadError::Record {
+            path: table.path.clone(),
+            record,
+            id: None,
+            field: Some(field.to_string()),
+            message,
+        };
+
+        let &cottage = cottage_index
+            .get(&x.cottage)
+            .ok_or_else(|| error("Cottage", format!("cottage {} does not exist", x.cottage)))?;
+        let first = x
+            .first
+            .date(options.timezone)
+            .map_err(|message| error("First Day", message))?;
+        let last = x
+            .last
+            .date(options.timezone)
+            .map_err(|message| error("Last Day", message))?;
+        if last < first {
+            return Err(error(
+                "Last Day",
+                format!("block ends on {} before it starts on {}", last, first),
+            ));
+        }
+
+        let start = (first - date_0).num_days().max(0) as usize;
+        let end = (last - date_0).num_days() + 1;
+        if end > 0 {
+            cottages.blocks[cottage].push(start..end as usize);
+        }
+    }
+
+    Ok(())
+}
+
+// JSON, or CSV if the path ends in `.csv`
+fn blocks_table(path: &str) -> Result<Table, ReadError> {
+    if path.to_lowercase().ends_with(".csv") {
+        Table::csv(path)
+    } else {
+        Table::json(path)
+    }
+}
+
+// Missing weights keep their default value
+pub fn read_weights_json(path: String) -> Result<CostWeights, ReadError> {
+    let json_string = read_file(&path)?;
+    serde_json::from_str(&json_string).map_err(|source| ReadError::Json { path, source })
+}
+
+fn parse_id(
+    path: &str,
+    line: usize,
+    id: Option<usize>,
+    field: &str,
+    s: &str,
+) -> Result<usize, ReadError> {
+    s.parse().map_err(|_| ReadError::Record {
+        path: path.to_string(),
+        record: line,
+        id,
+        field: Some(field.to_string()),
+        message: format!("`{}` is not a valid ID", s),
+    })
+}
+
+fn parse_cottage_id(
+    path: &str,
+    line: usize,
+    id: Option<usize>,
+    s: &str,
+) -> Result<Option<usize>, ReadError> {
+    match s {
+        "" | "-" => Ok(None),
+        cottage => parse_id(path, line, id, "cottage", cottage).map(Some),
+    }
+}
+
+// Cottage IDs of a solution file in the order of the reservations,
+// and the lines that were left out
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SolutionFile {
+    pub cottages: Vec<Option<usize>>,
+    // Reservation IDs that are not in the problem
+    pub unknown: Vec<usize>,
+    // Reservation IDs on more than one line, only the first is used
+    pub duplicates: Vec<usize>,
+}
+
+// Either one cottage ID per line, in reservation order, exactly one line per reservation,
+// or `<reservation ID> <cottage ID>` per line, in any order.
+// An empty cottage ID or `-` marks an unassigned reservation.
+// Lines are counted from zero in errors, like records.
+pub fn read_solution(path: String, reservations: &Reservations) -> Result<SolutionFile, ReadError> {
+    let solution_string = read_file(&path)?;
+    let lines: Vec<Vec<&str>> = solution_string
+        .lines()
+        .map(|line| {
+            line.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|x| !x.is_empty())
+                .collect()
+        })
+        .collect();
+
+    if lines.iter().all(|fields| fields.len() <= 1) {
+        if lines.len() != reservations.reservations() {
+            return Err(ReadError::LineCount {
+                path,
+                expected: reservations.reservations(),
+                found: lines.len(),
+            });
+        }
+        let cottages = lines
+            .iter()
+            .enumerate()
+            .map(|(line, fields)| {
+                parse_cottage_id(&path, line, None, fields.first().unwrap_or(&""))
+            })
+            .collect::<Result<_, _>>()?;
+        return Ok(SolutionFile {
+            cottages,
+            ..Default::default()
+        });
+    }
+
+    let position: HashMap<_, _> = reservations
+        .id
+        .iter()
+        .enumerate()
+        .map(|(index, &id)| (id, index))
+        .collect();
+
+    let mut file = SolutionFile {
+        cottages: vec![None; reservations.reservations()],
+        ..Default::default()
+    };
+    let mut seen = vec![false; reservations.reservations()];
+    for (line, fields) in lines.iter().enumerate() {
+        if fields.is_empty() {
+            continue;
+        }
+        let id = parse_id(&path, line, None, "reservation", fields[0])?;
+        let cottage = parse_cottage_id(&path, line, Some(id), fields.get(1).unwrap_or(&""))?;
+        match position.get(&id) {
+            None => file.unknown.push(id),
+            Some(&reservation) if seen[reservation] => file.duplicates.push(id),
+            Some(&reservation) => {
+                seen[reservation] = true;
+                file.cottages[reservation] = cottage;
+            }
         }
     }
+    file.duplicates.sort_unstable();
+    file.duplicates.dedup();
+    Ok(file)
+}
+
+pub fn read_problem_json(
+    cottages_path: String,
+    reservations_path: String,
+    options: &ReadOptions,
+) -> Result<Problem, ReadError> {
+    let cottages = Table::json(&cottages_path)?;
+    let reservations = Table::json(&reservations_path)?;
+    let blocks = options.blocks.as_deref().map(blocks_table).transpose()?;
+    problem_from_tables(&cottages, &reservations, blocks.as_ref(), options)
+}
 
-    (
-        phase,
-        Reservations::new(id, arrival, stay, people, class, preference, cottage_number),
-    )
+// CSV files with the same column headers as the JSON records
+pub fn read_problem_csv(
+    cottages_path: String,
+    reservations_path: String,
+    options: &ReadOptions,
+) -> Result<Problem, ReadError> {
+    let cottages = Table::csv(&cottages_path)?;
+    let reservations = Table::csv(&reservations_path)?;
+    let blocks = options.blocks.as_deref().map(blocks_table).transpose()?;
+    problem_from_tables(&cottages, &reservations, blocks.as_ref(), options)
 }
 
-pub fn read_problem_json(cottages_path: String, reservations_path: String) -> Problem {
-    let cottages = read_cottages_json(cottages_path);
-    let (phase, reservations) = read_reservations_json(reservations_path);
+// A workbook with a `Cottages` and a `Reservations` sheet
+pub fn read_problem_xlsx(path: String, options: &ReadOptions) -> Result<Problem, ReadError> {
+    let cottages = Table::xlsx(&path, "Cottages")?;
+    let reservations = Table::xlsx(&path, "Reservations")?;
+    let blocks = match &options.blocks {
+        Some(blocks) => Some(blocks_table(blocks)?),
+        None if Table::has_sheet(&path, "Blocks")? => Some(Table::xlsx(&path, "Blocks")?),
+        None => None,
+    };
+    problem_from_tables(&cottages, &reservations, blocks.as_ref(), options)
+}
+
+fn problem_from_tables(
+    cottages: &Table,
+    reservations: &Table,
+    blocks: Option<&Table>,
+    options: &ReadOptions,
+) -> Result<Problem, ReadError> {
+    let reservations_path = &reservations.path;
+    let cottages_path = &cottages.path;
+    let (mut amenities, mut cottages, mut ignored) = cottages_from_table(cottages, options)?;
+    for name in &options.soft_amenities {
+        let amenity = amenities
+            .index(name.trim())
+            .ok_or_else(|| ReadError::UnknownAmenity {
+                path: cottages_path.clone(),
+                name: name.clone(),
+            })?;
+        amenities.set_soft(amenity);
+    }
+    let read = reservations_from_table(reservations, &amenities, options)?;
+    let date_0 = read.date_0;
+    let mut reservations = read.reservations;
+    ignored.extend(read.ignored);
+    for (cottage, class) in cottages.class.iter().enumerate() {
+        cottages.turnover[cottage] = options.turnover.get(class).copied().unwrap_or(0);
+    }
+    if let Some(blocks) = blocks {
+        blocks_from_table(blocks, &mut cottages, date_0, options)?;
+    }
+
+    let mut capacities = cottages.capacity.clone();
+    capacities.sort_unstable();
+    capacities.dedup();
+    for (record, people) in reservations.people.iter_mut().enumerate() {
+        *people = round_people(&capacities, *people).ok_or_else(|| ReadError::Record {
+            path: reservations_path.clone(),
+            record,
+            id: Some(reservations.id[record]),
+            field: Some("# Persons".to_string()),
+            message: format!(
+                "party of {} does not fit any cottage, the largest holds {}",
+                people,
+                capacities.last().copied().unwrap_or(0)
+            ),
+        })?;
+    }
+
+    // Fixed cottages are numbered from one, in file order
+    for (record, fixed) in reservations.cottage_number.iter().enumerate() {
+        if let Some(fixed) = *fixed {
+            if fixed >= cottages.cottages() {
+                return Err(ReadError::Record {
+                    path: reservations_path.clone(),
+                    record,
+                    id: Some(reservations.id[record]),
+                    field: Some("Cottage (Fixed)".to_string()),
+                    message: format!(
+                        "cottage {} does not exist, there are {} cottages",
+                        fixed + 1,
+                        cottages.cottages()
+                    ),
+                });
+            }
+        }
+    }
+
+    let mut problem = Problem::with_amenities(cottages, reservations, phase(date_0), amenities);
+    problem.horizon = Some(read.horizon);
+    problem.skipped = read.skipped;
+    problem.ignored_columns = ignored.into_iter().collect();
+    Ok(problem)
+}
+
+#[cfg(test)]
+mod test {
+    use calamine::{ExcelDateTime, ExcelDateTimeType};
+
+    use crate::feasibility::{check_feasibility, diagnose, Exclusion, Infeasibility};
+
+    use super::*;
+
+    fn write_file(name: &str, contents: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("or-challenge-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn cottage(id: usize, capacity: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "ID": id, "Max # Pers": capacity, "Class": 1,
+            "Face South": 0, "Near Playground": 0, "Close to the Centre": 0,
+            "Near Lake ": 0, "Near car park": 0, "Accessible for Wheelchair": 0,
+            "Child Friendly": 0, "Dish Washer ": 0, "Wi-Fi Coverage ": 0, "Covered Terrace": 0,
+        })
+    }
+
+    fn reservation(id: usize, fixed: usize) -> serde_json::Value {
+        serde_json::json!({
+            "ID": id, "Arrival Date": 1656633600000i64, "Length of Stay": 2, "# Persons": 2,
+            "Class": 1, "Face South": 0, "Near Playground": 0, "Close to the Centre": 0,
+            "Near Lake ": 0, "Near car park": 0, "Accessible for Wheelchair": 0,
+            "Child Friendly": 0, "Dish Washer ": 0, "Wi-Fi Coverage ": 0, "Covered Terrace": 0,
+            "Cottage (Fixed)": fixed,
+        })
+    }
+
+    #[test]
+    fn test_read_problem() {
+        let cottages = write_file(
+            "cottages.json",
+            &serde_json::json!([cottage(7, 4.into())]).to_string(),
+        );
+        let reservations = write_file(
+            "reservations.json",
+            &serde_json::json!([reservation(3, 1)]).to_string(),
+        );
+
+        let problem = read_problem_json(cottages, reservations, &ReadOptions::default()).unwrap();
+        assert_eq!(problem.cottages.id, vec![7]);
+        assert_eq!(problem.reservations.id, vec![3]);
+        assert_eq!(problem.reservations.cottage_number, vec![Some(0)]);
+        assert_eq!(problem.reservations.people, vec![4]);
+    }
+
+    #[test]
+    fn test_read_problem_csv() {
+        let cottages = write_file(
+            "cottages.csv",
+            "ID,Max # Pers,Class,Face South,Near Playground,Close to the Centre,Near Lake ,\
+             Near car park,Accessible for Wheelchair,Child Friendly,Dish Washer ,\
+             Wi-Fi Coverage ,Covered Terrace\n\
+             7,4.0,1,0,0,1,0,0,0,0,0,0,0\n",
+        );
+        let reservations = write_file(
+            "reservations.csv",
+            "ID,Arrival Date,Length of Stay,# Persons,Class,Face South,Near Playground,\
+             Close to the Centre,Near Lake ,Near car park,Accessible for Wheelchair,\
+             Child Friendly,Dish Washer ,Wi-Fi Coverage ,Covered Terrace,Cottage (Fixed)\n\
+             3,2022-07-02,2,3,1,0,0,1,0,0,0,0,0,0,0,0\n\
+             4,1656633600000,1,2,1,0,0,0,0,0,0,0,0,0,0,1\n",
+        );
+
+        let problem = read_problem_csv(cottages, reservations, &ReadOptions::default()).unwrap();
+        assert_eq!(problem.cottages.id, vec![7]);
+        assert_eq!(problem.cottages.capacity, vec![4]);
+        let centre = problem.amenities.index("Close to the Centre").unwrap();
+        assert!(problem.cottages.preference[0].contains(centre));
+        assert_eq!(problem.amenities.len(), 10);
+        assert_eq!(problem.reservations.arrival(), vec![1, 0]);
+        assert_eq!(problem.reservations.people, vec![4, 4]);
+        assert_eq!(problem.reservations.cottage_number, vec![None, Some(0)]);
+    }
+
+    #[test]
+    fn test_table_cells() {
+        let date = NaiveDate::from_ymd_opt(2022, 7, 1).unwrap();
+        let rows = [
+            vec![
+                Data::String("ID".to_string()),
+                Data::String("Arrival Date".to_string()),
+                Data::String("Class".to_string()),
+            ],
+            vec![
+                Data::Float(12.0),
+                Data::DateTime(ExcelDateTime::new(
+                    44743.5,
+                    ExcelDateTimeType::DateTime,
+                    false,
+                )),
+                Data::Empty,
+            ],
+            vec![Data::Empty, Data::Empty, Data::Empty],
+            vec![
+                Data::Int(13),
+                Data::String(date.to_string()),
+                Data::Float(1.5),
+            ],
+        ];
+
+        let table = Table::cells("test".to_string(), rows.iter().map(|x| &x[..]));
+        assert_eq!(
+            table.records,
+            vec![
+                serde_json::json!({"ID": 12, "Arrival Date": "2022-07-01T12:00:00"}),
+                serde_json::json!({"ID": 13, "Arrival Date": "2022-07-01", "Class": 1.5}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_bad_field() {
+        let path = write_file(
+            "bad_field.json",
+            &serde_json::json!([cottage(1, 4.into()), cottage(2, "four".into())]).to_string(),
+        );
+
+        match read_cottages_json(path, &ReadOptions::default()) {
+            Err(ReadError::Record {
+                record, id, field, ..
+            }) => {
+                assert_eq!(record, 1);
+                assert_eq!(id, Some(2));
+                assert_eq!(field.as_deref(), Some("Max # Pers"));
+            }
+            x => panic!("expected a record error, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_read_unknown_fixed_cottage() {
+        let cottages = write_file(
+            "fixed_cottages.json",
+            &serde_json::json!([cottage(1, 4.into())]).to_string(),
+        );
+        let reservations = write_file(
+            "fixed_reservations.json",
+            &serde_json::json!([reservation(5, 1), reservation(6, 2)]).to_string(),
+        );
+
+        match read_problem_json(cottages, reservations, &ReadOptions::default()) {
+            Err(ReadError::Record {
+                record, id, field, ..
+            }) => {
+                assert_eq!(record, 1);
+                assert_eq!(id, Some(6));
+                assert_eq!(field.as_deref(), Some("Cottage (Fixed)"));
+            }
+            x => panic!("expected a record error, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_arrival_date() {
+        let utc = Tz::UTC;
+        let amsterdam: Tz = "Europe/Amsterdam".parse().unwrap();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        // 2022-06-30T22:00:00Z
+        let millis = ArrivalDate::Millis(1656626400000);
+        assert_eq!(millis.date(utc), Ok(date(2022, 6, 30)));
+        assert_eq!(millis.date(amsterdam), Ok(date(2022, 7, 1)));
+
+        let text = |x: &str| ArrivalDate::Text(x.to_string());
+        assert_eq!(text("2022-07-01").date(utc), Ok(date(2022, 7, 1)));
+        assert_eq!(
+            text("2022-07-01T23:30:00").date(amsterdam),
+            Ok(date(2022, 7, 1))
+        );
+        assert_eq!(
+            text("2022-06-30T23:30:00+00:00").date(amsterdam),
+            Ok(date(2022, 7, 1))
+        );
+        assert!(text("01/07/2022").date(utc).is_err());
+    }
+
+    #[test]
+    fn test_read_earliest_arrival() {
+        let mut first = reservation(1, 0);
+        first["Arrival Date"] = "2022-07-03".into();
+        // 2022-07-01T00:00:00Z, a Friday
+        let earliest = reservation(2, 0);
+        let path = write_file(
+            "earliest.json",
+            &serde_json::json!([first, earliest]).to_string(),
+        );
+
+        let read = read_reservations_json(path, &AmenitySchema::default(), &ReadOptions::default())
+            .unwrap();
+        assert_eq!(read.phase(), 0);
+        assert_eq!(read.horizon, 0..3);
+        assert_eq!(read.reservations.arrival(), vec![2, 0]);
+        assert!(read.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_read_outside_horizon() {
+        // 2022-07-01 for 1 day, 2022-07-03 for 3 days and 2022-07-08 for 1 day
+        let mut before = reservation(1, 0);
+        before["Length of Stay"] = 1.into();
+        let mut straddling = reservation(2, 0);
+        straddling["Arrival Date"] = "2022-07-03".into();
+        straddling["Length of Stay"] = 3.into();
+        let mut after = reservation(3, 0);
+        after["Arrival Date"] = "2022-07-08".into();
+        after["Length of Stay"] = 1.into();
+        let path = write_file(
+            "horizon.json",
+            &serde_json::json!([before, straddling, after]).to_string(),
+        );
+
+        let options = ReadOptions {
+            horizon_start: NaiveDate::from_ymd_opt(2022, 7, 4),
+            horizon_end: NaiveDate::from_ymd_opt(2022, 7, 8),
+            ..Default::default()
+        };
+        let read = read_reservations_json(path, &AmenitySchema::default(), &options).unwrap();
+        assert_eq!(read.skipped, vec![1, 3]);
+        assert_eq!(read.reservations.id, vec![2]);
+        assert_eq!(read.reservations.arrival(), vec![0]);
+        assert_eq!(read.reservations.stay(), vec![3]);
+        assert_eq!(read.horizon, 1..5);
+    }
 
-    Problem::new(cottages, reservations, phase)
+    #[test]
+    fn test_read_inverted_horizon() {
+        let path = write_file(
+            "inverted_horizon.json",
+            &serde_json::json!([reservation(1, 0)]).to_string(),
+        );
+
+        let options = ReadOptions {
+            horizon_start: NaiveDate::from_ymd_opt(2022, 7, 8),
+            horizon_end: NaiveDate::from_ymd_opt(2022, 7, 4),
+            ..Default::default()
+        };
+        assert!(matches!(
+            read_reservations_json(path, &AmenitySchema::default(), &options),
+            Err(ReadError::Horizon { .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_unknown_amenity() {
+        let cottages = write_file(
+            "amenity_cottages.json",
+            &serde_json::json!([cottage(7, 4.into())]).to_string(),
+        );
+        let mut first = reservation(3, 0);
+        first["Sauna"] = 1.into();
+        let reservations = write_file(
+            "amenity_reservations.json",
+            &serde_json::json!([reservation(2, 0), first]).to_string(),
+        );
+
+        // Not a column of the cottages, so not a requirement either
+        let problem = read_problem_json(
+            cottages.clone(),
+            reservations.clone(),
+            &ReadOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(problem.ignored_columns, vec!["Sauna".to_string()]);
+        assert!(problem.reservations.preference[1].is_empty());
+
+        // Listed, but no cottage has the column
+        let options = ReadOptions {
+            amenities: Some(vec!["Sauna".to_string()]),
+            ..ReadOptions::default()
+        };
+        match read_problem_json(cottages, reservations, &options).unwrap_err() {
+            ReadError::UnknownAmenity { name, .. } => assert_eq!(name, "Sauna"),
+            other => panic!("unexpected error {other}"),
+        }
+    }
+
+    #[test]
+    fn test_read_new_amenity() {
+        let mut sauna = cottage(7, 4.into());
+        sauna["Sauna"] = 1.into();
+        sauna["Price"] = 120.into();
+        let mut plain = cottage(8, 4.into());
+        plain["Sauna"] = 0.into();
+        plain["Price"] = 95.into();
+        let cottages = write_file(
+            "new_amenity_cottages.json",
+            &serde_json::json!([sauna, plain]).to_string(),
+        );
+        let mut wish = reservation(3, 0);
+        wish["Sauna"] = 1.into();
+        let reservations = write_file(
+            "new_amenity_reservations.json",
+            &serde_json::json!([wish]).to_string(),
+        );
+
+        let problem = read_problem_json(cottages, reservations, &ReadOptions::default()).unwrap();
+        assert_eq!(problem.amenities.len(), 11);
+        assert_eq!(problem.amenities.names()[0], "Face South");
+        assert_eq!(problem.amenities.index("Sauna"), Some(10));
+        assert_eq!(problem.ignored_columns, vec!["Price".to_string()]);
+        assert_eq!(problem.allocation_penalty.possible_targets(0), &[0]);
+    }
+
+    #[test]
+    fn test_read_amenity_columns() {
+        let cottages = write_file(
+            "columns_cottages.csv",
+            "ID,Max # Pers,Class,Sauna,Notes,EV charger\n\
+             1,4,1,0,renovated,1\n\
+             2,4,1,1,,0\n",
+        );
+        let reservations = write_file(
+            "columns_reservations.csv",
+            "ID,Arrival Date,Length of Stay,# Persons,Class,Cottage (Fixed),EV charger,Remarks\n\
+             3,2022-07-01,2,2,1,0,1,late arrival\n",
+        );
+
+        // Text columns are not amenities
+        let options = ReadOptions::default();
+        let problem = read_problem_csv(cottages.clone(), reservations.clone(), &options).unwrap();
+        assert_eq!(problem.amenities.names(), &["Sauna", "EV charger"]);
+        assert_eq!(
+            problem.ignored_columns,
+            vec!["Notes".to_string(), "Remarks".to_string()]
+        );
+        assert_eq!(problem.allocation_penalty.possible_targets(0), &[0]);
+
+        // A list overrides the header
+        let listed = ReadOptions {
+            amenities: Some(vec!["EV charger".to_string()]),
+            ..ReadOptions::default()
+        };
+        let problem = read_problem_csv(cottages.clone(), reservations, &listed).unwrap();
+        assert_eq!(problem.amenities.names(), &["EV charger"]);
+        assert_eq!(
+            problem.ignored_columns,
+            vec![
+                "Notes".to_string(),
+                "Remarks".to_string(),
+                "Sauna".to_string()
+            ]
+        );
+
+        // Amenity cells are 0 or 1
+        let reservations = write_file(
+            "columns_bad_reservations.csv",
+            "ID,Arrival Date,Length of Stay,# Persons,Class,Cottage (Fixed),EV charger\n\
+             3,2022-07-01,2,2,1,0,yes\n",
+        );
+        match read_problem_csv(cottages, reservations, &options).unwrap_err() {
+            ReadError::Record { id, field, .. } => {
+                assert_eq!(id, Some(3));
+                assert_eq!(field.as_deref(), Some("EV charger"));
+            }
+            other => panic!("unexpected error {other}"),
+        }
+    }
+
+    #[test]
+    fn test_read_soft_amenity() {
+        let mut lake = cottage(7, 4.into());
+        lake["Near Lake "] = 1.into();
+        let cottages = write_file(
+            "soft_cottages.json",
+            &serde_json::json!([cottage(6, 4.into()), lake]).to_string(),
+        );
+        let mut wish = reservation(3, 0);
+        wish["Near Lake "] = 1.into();
+        let reservations = write_file(
+            "soft_reservations.json",
+            &serde_json::json!([wish]).to_string(),
+        );
+
+        let problem = read_problem_json(
+            cottages.clone(),
+            reservations.clone(),
+            &ReadOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(problem.allocation_penalty.possible_targets(0), &[1]);
+
+        let options = ReadOptions {
+            soft_amenities: vec!["Near Lake".to_string()],
+            ..ReadOptions::default()
+        };
+        let problem = read_problem_json(cottages.clone(), reservations.clone(), &options).unwrap();
+        assert!(problem
+            .amenities
+            .is_soft(problem.amenities.index("Near Lake").unwrap()));
+        assert_eq!(problem.allocation_penalty.possible_targets(0), &[0, 1]);
+
+        let options = ReadOptions {
+            soft_amenities: vec!["Sauna".to_string()],
+            ..ReadOptions::default()
+        };
+        let error = read_problem_json(cottages, reservations, &options).unwrap_err();
+        assert!(matches!(error, ReadError::UnknownAmenity { name, .. } if name == "Sauna"));
+    }
+
+    #[test]
+    fn test_read_blocks() {
+        let cottages = write_file(
+            "block_cottages.json",
+            &serde_json::json!([cottage(6, 4.into()), cottage(7, 4.into())]).to_string(),
+        );
+        let mut later = reservation(4, 0);
+        later["Arrival Date"] = "2022-07-10".into();
+        let reservations = write_file(
+            "block_reservations.json",
+            &serde_json::json!([reservation(3, 0), later]).to_string(),
+        );
+        let blocks = write_file(
+            "blocks.csv",
+            "Cottage,First Day,Last Day,Reason\n\
+             7,2022-07-02,2022-07-04,Painting\n\
+             6,2022-06-01,2022-06-30,Before day 0\n",
+        );
+
+        let options = ReadOptions {
+            blocks: Some(blocks),
+            ..ReadOptions::default()
+        };
+        let problem = read_problem_json(cottages.clone(), reservations.clone(), &options).unwrap();
+        assert_eq!(problem.cottages.blocks, vec![vec![], vec![1..4]]);
+        assert_eq!(problem.allocation_penalty.possible_targets(0), &[0]);
+        assert_eq!(problem.allocation_penalty.possible_targets(1), &[0, 1]);
+
+        let blocks = write_file(
+            "bad_blocks.json",
+            &serde_json::json!([
+                {"Cottage": 7, "First Day": "2022-07-04", "Last Day": "2022-07-02"},
+            ])
+            .to_string(),
+        );
+        let options = ReadOptions {
+            blocks: Some(blocks),
+            ..ReadOptions::default()
+        };
+        let error =
+            read_problem_json(cottages.clone(), reservations.clone(), &options).unwrap_err();
+        assert!(
+            matches!(error, ReadError::Record { field: Some(field), .. } if field == "Last Day")
+        );
+
+        // A fixed stay in a blocked cottage can never be met,
+        // that is for the feasibility check to report
+        let reservations = write_file(
+            "fixed_block_reservations.json",
+            &serde_json::json!([reservation(3, 2)]).to_string(),
+        );
+        let blocks = write_file(
+            "fixed_blocks.json",
+            &serde_json::json!([
+                {"Cottage": 7, "First Day": "2022-07-02", "Last Day": "2022-07-02"},
+            ])
+            .to_string(),
+        );
+        let options = ReadOptions {
+            blocks: Some(blocks),
+            ..ReadOptions::default()
+        };
+        let problem = read_problem_json(cottages, reservations, &options).unwrap();
+        assert_eq!(
+            check_feasibility(&problem),
+            vec![Infeasibility::NoCottage { reservation: 0 }]
+        );
+        assert_eq!(
+            diagnose(&problem, 0).exclusions,
+            vec![
+                (Exclusion::FixedCottage { fixed: 1 }, vec![0]),
+                (Exclusion::Blocked, vec![1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_people() {
+        let capacities = [2, 4, 5, 6, 8, 12];
+        assert_eq!(round_people(&capacities, 1), Some(2));
+        assert_eq!(round_people(&capacities, 3), Some(4));
+        assert_eq!(round_people(&capacities, 5), Some(5));
+        assert_eq!(round_people(&capacities, 9), Some(12));
+        assert_eq!(round_people(&capacities, 13), None);
+        assert_eq!(round_people(&[], 1), None);
+    }
+
+    #[test]
+    fn test_read_party_too_large() {
+        let cottages = write_file(
+            "large_cottages.json",
+            &serde_json::json!([cottage(1, 4.into())]).to_string(),
+        );
+        let mut large = reservation(8, 0);
+        large["# Persons"] = 5.into();
+        let reservations = write_file(
+            "large_reservations.json",
+            &serde_json::json!([reservation(7, 0), large]).to_string(),
+        );
+
+        match read_problem_json(cottages, reservations, &ReadOptions::default()) {
+            Err(ReadError::Record {
+                record, id, field, ..
+            }) => {
+                assert_eq!(record, 1);
+                assert_eq!(id, Some(8));
+                assert_eq!(field.as_deref(), Some("# Persons"));
+            }
+            x => panic!("expected a record error, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_read_missing_file() {
+        let path = "/nonexistent/cottages.json".to_string();
+        assert!(matches!(
+            read_cottages_json(path, &ReadOptions::default()),
+            Err(ReadError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_solution_errors() {
+        let reservations = Reservations::empty(2);
+        let path = write_file("solution.txt", "3\nx\n");
+
+        match read_solution(path, &reservations) {
+            Err(ReadError::Record { record, field, .. }) => {
+                assert_eq!(record, 1);
+                assert_eq!(field.as_deref(), Some("cottage"));
+            }
+            x => panic!("expected a record error, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_read_solution_line_count() {
+        let reservations = Reservations::empty(3);
+        let path = write_file("positional.txt", "3\n-\n");
+
+        assert!(matches!(
+            read_solution(path, &reservations),
+            Err(ReadError::LineCount {
+                expected: 3,
+                found: 2,
+                ..
+            })
+        ));
+
+        // With IDs the lines are matched, missing ones stay unassigned
+        let mut reservations = Reservations::empty(3);
+        reservations.id = vec![7, 8, 9];
+        let path = write_file("with-ids.txt", "9 3\n7 -\n");
+        assert_eq!(
+            read_solution(path, &reservations).unwrap().cottages,
+            vec![None, None, Some(3)]
+        );
+    }
+
+    #[test]
+    fn test_read_solution_ignored_ids() {
+        let mut reservations = Reservations::empty(3);
+        reservations.id = vec![7, 8, 9];
+        let path = write_file("ignored-ids.txt", "9 3\n5 1\n9 4\n7 2\n9 -\n");
+
+        let file = read_solution(path, &reservations).unwrap();
+        assert_eq!(file.cottages, vec![Some(2), None, Some(3)]);
+        assert_eq!(file.unknown, vec![5]);
+        assert_eq!(file.duplicates, vec![9]);
+    }
 }
@@ -0,0 +1,237 @@
+use std::fmt;
+
+use crate::{
+    allocation_penalty::Penalty,
+    cost::{Cost, CostWeights},
+    gap_cost::calculate_cottage_gaps2,
+    problem::Problem,
+    solution::Solution,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    pub name: &'static str,
+    pub count: isize,
+    pub weight: isize,
+}
+
+impl Component {
+    pub fn contribution(&self) -> isize {
+        self.count * self.weight
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CottageGaps {
+    pub cottage: usize,
+    pub gaps: usize,
+    pub gaps_fri_thu: usize,
+    pub gaps_legionella: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upgrade {
+    pub reservation: usize,
+    pub cottage: usize,
+    pub cost: usize,
+    pub reservation_class: usize,
+    pub cottage_class: usize,
+    pub people: usize,
+    pub capacity: usize,
+}
+
+// Breakdown of the objective of a solution.
+// Cottages and reservations are listed by index, `Display` prints their IDs.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub objective: isize,
+    pub components: Vec<Component>,
+    pub cottage_gaps: Vec<CottageGaps>,
+    pub upgrades: Vec<Upgrade>,
+    cottage_ids: Vec<usize>,
+    reservation_ids: Vec<usize>,
+}
+
+impl Report {
+    pub fn new(problem: &Problem, solution: &Solution) -> Self {
+        let cost = Cost::calculate(problem, solution);
+        let weights = &problem.weights;
+
+        let cottage_gaps = (0..problem.cottages.cottages())
+            .map(|cottage| {
+                let (gaps, gaps_fri_thu, gaps_legionella) =
+                    calculate_cottage_gaps2(problem, solution, cottage);
+                CottageGaps {
+                    cottage,
+                    gaps,
+                    gaps_fri_thu,
+                    gaps_legionella,
+                }
+            })
+            .filter(|x| x.gaps > 0)
+            .collect();
+
+        let upgrades = solution
+            .mapping()
+            .iter()
+            .enumerate()
+            .filter_map(|(reservation, cottage)| cottage.map(|cottage| (reservation, cottage)))
+            .filter_map(|(reservation, cottage)| {
+                match problem.allocation_penalty.get(cottage, reservation) {
+                    Penalty::Upgrade(cost) => Some((reservation, cottage, cost)),
+                    _ => None,
+                }
+            })
+            .map(|(reservation, cottage, cost)| Upgrade {
+                reservation,
+                cottage,
+                cost,
+                reservation_class: problem.reservations.class[reservation],
+                cottage_class: problem.cottages.class[cottage],
+                people: problem.reservations.people[reservation],
+                capacity: problem.cottages.capacity[cottage],
+            })
+            .collect();
+
+        Self {
+            objective: cost.objective(weights),
+            components: Self::components(&cost, weights),
+            cottage_gaps,
+            upgrades,
+            cottage_ids: problem.cottages.id.clone(),
+            reservation_ids: problem.reservations.id.clone(),
+        }
+    }
+
+    fn components(cost: &Cost, weights: &CostWeights) -> Vec<Component> {
+        vec![
+            Component {
+                name: "gaps",
+                count: cost.gaps(),
+                weight: weights.gaps,
+            },
+            Component {
+                name: "gaps_fri_thu",
+                count: cost.gaps_fri_thu(),
+                weight: weights.gaps_fri_thu,
+            },
+            Component {
+                name: "gaps_legionella",
+                count: cost.gaps_legionella(),
+                weight: weights.gaps_legionella,
+            },
+            Component {
+                name: "upgrades",
+                count: cost.upgrades(),
+                weight: weights.upgrades,
+            },
+            Component {
+                name: "preferences",
+                count: cost.preferences(),
+                weight: weights.preferences,
+            },
+            Component {
+                name: "unallocated",
+                count: cost.unallocated(),
+                weight: weights.unallocated,
+            },
+        ]
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Objective: {}", self.objective)?;
+        writeln!(
+            f,
+            "{:<16} {:>8} {:>8} {:>12}",
+            "component", "count", "weight", "contribution"
+        )?;
+        for component in &self.components {
+            writeln!(
+                f,
+                "{:<16} {:>8} {:>8} {:>12}",
+                component.name,
+                component.count,
+                component.weight,
+                component.contribution()
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Gaps per cottage:")?;
+        writeln!(
+            f,
+            "{:>8} {:>6} {:>8} {:>10}",
+            "cottage", "gaps", "fri_thu", "legionella"
+        )?;
+        for gaps in &self.cottage_gaps {
+            writeln!(
+                f,
+                "{:>8} {:>6} {:>8} {:>10}",
+                self.cottage_ids[gaps.cottage], gaps.gaps, gaps.gaps_fri_thu, gaps.gaps_legionella
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Upgraded reservations:")?;
+        writeln!(
+            f,
+            "{:>12} {:>8} {:>12} {:>14} {:>6}",
+            "reservation", "cottage", "class", "people", "cost"
+        )?;
+        for upgrade in &self.upgrades {
+            writeln!(
+                f,
+                "{:>12} {:>8} {:>12} {:>14} {:>6}",
+                self.reservation_ids[upgrade.reservation],
+                self.cottage_ids[upgrade.cottage],
+                format!("{} -> {}", upgrade.reservation_class, upgrade.cottage_class),
+                format!("{} -> {}", upgrade.people, upgrade.capacity),
+                upgrade.cost,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::problem::{Cottages, Reservations};
+
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut cottages = Cottages::empty(2);
+        let mut reservations = Reservations::empty(2);
+        cottages.class = vec![1, 2];
+        reservations.class = vec![1, 1];
+        reservations.set_arrival(vec![0, 2]);
+        reservations.set_stay(vec![1, 3]);
+
+        let problem = Problem::new(cottages, reservations, 0);
+        let solution = Solution::naive(&problem);
+        let report = Report::new(&problem, &solution);
+        let cost = Cost::calculate(&problem, &solution);
+
+        assert_eq!(report.objective, cost.objective(&problem.weights));
+        assert_eq!(
+            report
+                .components
+                .iter()
+                .map(|x| x.contribution())
+                .sum::<isize>(),
+            report.objective
+        );
+
+        // c0: #____, c1: __###
+        assert_eq!(report.cottage_gaps.len(), 2);
+        assert_eq!(report.cottage_gaps[0].gaps, 1);
+        assert_eq!(report.cottage_gaps[1].gaps, 1);
+
+        assert_eq!(report.upgrades.len(), 1);
+        assert_eq!(report.upgrades[0].reservation, 1);
+        assert_eq!(report.upgrades[0].cottage_class, 2);
+    }
+}
@@ -1,45 +1,289 @@
-use crate::{instance::Instance, problem::Problem, solution::Solution};
+use std::time::{Duration, Instant};
 
-pub fn run_simulated_annealing(problem: Problem, id: usize) -> Instance {
+use crate::{
+    cooling::{CoolingConfig, Progress, MIN_TEMPERATURE},
+    instance::{Instance, RepairError},
+    problem::Problem,
+    solution::Solution,
+};
+
+#[derive(Debug, Clone)]
+pub struct SolverConfig {
+    // Wall-clock budget for the annealing loop
+    pub time_limit: Duration,
+    // Maximum number of neighbor moves
+    pub iterations: u64,
+    // The clock is read once every `check_interval` moves,
+    // so the deadline is overshot by at most that many moves
+    pub check_interval: u64,
+    // Master seed, each thread derives its own seed from this
+    pub seed: u64,
+    pub cooling: CoolingConfig,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self {
+            time_limit: Duration::from_secs(50 * 60),
+            iterations: 1500000000,
+            check_interval: 10000,
+            seed: 0,
+            cooling: CoolingConfig::default(),
+        }
+    }
+}
+
+// SplitMix64 finalizer over the master seed and thread id,
+// so neighboring thread ids get unrelated streams
+pub fn thread_seed(seed: u64, id: usize) -> u64 {
+    let mut z = seed ^ (id as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+pub fn run_simulated_annealing(
+    problem: Problem,
+    id: usize,
+    config: &SolverConfig,
+) -> Result<Instance, RepairError> {
     let solution = Solution::empty(&problem);
-    let mut instance = Instance::new(problem, solution);
-    println!("Generating initial solution on thread {id}");
+    run_simulated_annealing_from(problem, solution, id, config)
+}
+
+// Anneal starting from `solution`, unallocated reservations are repaired first.
+// Fails if they can not all be placed and the problem does not allow unallocated ones.
+pub fn run_simulated_annealing_from(
+    problem: Problem,
+    solution: Solution,
+    id: usize,
+    config: &SolverConfig,
+) -> Result<Instance, RepairError> {
+    // Repair and calibration count toward the time limit
+    let now = Instant::now();
+    let seed = thread_seed(config.seed, id);
+    let mut instance = Instance::with_seed(problem, solution, seed);
+    println!("Generating initial solution on thread {id} with seed {seed}");
 
-    instance.repair();
+    instance.repair()?;
     instance.accept_chain();
     println!("Initial solution generated on {id}");
 
-    let now = std::time::Instant::now();
-    let max_runtime_mins = 50f64;
+    let (start, end) = config.cooling.calibrate(&mut instance);
+    let cooling = config.cooling.build(start, end);
+    println!("T{id}: cooling from {start} to {end}");
+
+    let n = config.iterations;
+    let check_interval = config.check_interval.max(1);
+    let report_interval = (n / 50).max(1);
+    let time_limit = config.time_limit.as_secs_f64();
+    let mut elapsed = 0f64;
 
-    let n: i64 = 1500000000;
     for i in 0..n {
         instance.neighbor();
 
-        let chain_cost = instance.chain.cost().objective();
+        let chain_cost = instance.chain.cost().objective(&instance.problem.weights);
 
-        if chain_cost < 0 {
+        if chain_cost <= 0 {
             instance.accept_chain();
         } else {
-            let temp = 1f64 - ((i + 1) as f64 / n as f64);
-            let temp = 0.2 * temp;
-            let criterion = (-chain_cost as f64 / temp as f64).exp();
-            if fastrand::f64() < criterion {
+            let progress = Progress {
+                iteration: i + 1,
+                iterations: n,
+                elapsed,
+            };
+            let temp = cooling.temperature(&progress);
+            if instance.rand_f64() < criterion(chain_cost, temp) {
                 instance.accept_chain();
             } else {
                 instance.reject_chain();
             }
-            if i % (n / 50) == 0 {
-                println!("T{}: {}%", id, (i * 100) as f64 / n as f64);
-                if now.elapsed().as_secs_f64() > max_runtime_mins * 60f64 {
-                    println!("T{}: breaking, time limit reached", id);
-                    break;
-                }
+        }
+
+        if i % report_interval == 0 {
+            println!("T{}: {}%", id, (i * 100) as f64 / n as f64);
+        }
+
+        if (i + 1) % check_interval == 0 {
+            elapsed = now.elapsed().as_secs_f64() / time_limit;
+            if elapsed > 1f64 {
+                println!("T{}: breaking, time limit reached", id);
+                break;
             }
         }
     }
 
     println!("Thread {id} done");
 
-    return instance;
+    Ok(instance)
+}
+
+// Probability of accepting a move that makes the objective worse by `chain_cost`.
+// Linear schedules cool to 0, the temperature is kept above it so this is never NaN
+fn criterion(chain_cost: isize, temperature: f64) -> f64 {
+    (-chain_cost as f64 / temperature.max(MIN_TEMPERATURE)).exp()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        cooling::Schedule,
+        cost::Cost,
+        problem::{Cottages, Reservations},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_time_limit() {
+        let problem = Problem::empty(4, 2);
+        let config = SolverConfig {
+            time_limit: Duration::from_millis(50),
+            iterations: u64::MAX,
+            check_interval: 100,
+            seed: 0,
+            ..Default::default()
+        };
+
+        let now = Instant::now();
+        let instance = run_simulated_annealing(problem, 0, &config).unwrap();
+        assert!(now.elapsed() < Duration::from_secs(10));
+        assert!(instance.solution.unallocated().is_empty());
+    }
+
+    #[test]
+    fn test_iteration_limit() {
+        let problem = Problem::empty(4, 2);
+        let config = SolverConfig {
+            time_limit: Duration::from_secs(3600),
+            iterations: 1000,
+            check_interval: 1,
+            seed: 0,
+            ..Default::default()
+        };
+
+        let instance = run_simulated_annealing(problem, 0, &config).unwrap();
+        assert!(instance.solution.unallocated().is_empty());
+    }
+
+    #[test]
+    fn test_reproducible() {
+        let mut cottages = Cottages::empty(6);
+        let mut reservations = Reservations::empty(8);
+        cottages.class = vec![0, 0, 1, 1, 2, 2];
+        reservations.set_arrival(vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        reservations.set_stay(vec![3, 2, 4, 1, 3, 2, 1, 2]);
+        let problem = Problem::new(cottages, reservations, 0);
+
+        let config = SolverConfig {
+            time_limit: Duration::from_secs(3600),
+            iterations: 2000,
+            check_interval: 100,
+            seed: 42,
+            ..Default::default()
+        };
+
+        let a = run_simulated_annealing(problem.clone(), 3, &config).unwrap();
+        let b = run_simulated_annealing(problem, 3, &config).unwrap();
+        assert_eq!(a.solution.mapping(), b.solution.mapping());
+        assert_eq!(a.solution.validate(&a.problem), Ok(()));
+    }
+
+    #[test]
+    fn test_calibrated_geometric() {
+        let problem = Problem::empty(6, 4);
+        let config = SolverConfig {
+            iterations: 1000,
+            cooling: CoolingConfig {
+                schedule: Schedule::Geometric,
+                start: None,
+                end: None,
+                samples: 100,
+                acceptance: 0.8,
+            },
+            ..Default::default()
+        };
+
+        let instance = run_simulated_annealing(problem, 0, &config).unwrap();
+        assert!(instance.solution.unallocated().is_empty());
+    }
+
+    #[test]
+    fn test_warm_start() {
+        let problem = Problem::empty(4, 3);
+        let solution = Solution::warm_start(&problem, &[Some(0), Some(0), None]);
+        assert_eq!(solution.unallocated(), &[1, 2]);
+
+        let config = SolverConfig {
+            iterations: 100,
+            ..Default::default()
+        };
+        let instance = run_simulated_annealing_from(problem, solution, 0, &config).unwrap();
+        assert_eq!(instance.solution.validate(&instance.problem), Ok(()));
+    }
+
+    #[test]
+    fn test_partial_allocation() {
+        // Three stays on the same day, two cottages, and a party too large for both
+        let mut cottages = Cottages::empty(2);
+        cottages.capacity = vec![4, 4];
+        let mut reservations = Reservations::empty(4);
+        reservations.people = vec![2, 4, 4, 6];
+        let mut problem = Problem::new(cottages, reservations, 0);
+        problem.allow_unallocated = true;
+
+        let config = SolverConfig {
+            iterations: 500,
+            ..Default::default()
+        };
+        let instance = run_simulated_annealing(problem, 0, &config).unwrap();
+        assert_eq!(instance.solution.validate(&instance.problem), Ok(()));
+        assert_eq!(instance.solution.unallocated().len(), 2);
+        assert!(instance.solution.unallocated().contains(&3));
+        assert_eq!(Cost::calculate_instance(&instance).unallocated(), 2);
+    }
+
+    #[test]
+    fn test_unrepairable() {
+        // Two cottages are taken on days 0 and 1, and on day 2 for the turnover,
+        // so a third stay on day 2 can not be placed
+        let mut cottages = Cottages::empty(2);
+        cottages.turnover = vec![1, 1];
+        let mut reservations = Reservations::empty(3);
+        reservations.id = vec![10, 11, 12];
+        reservations.set_arrival(vec![0, 0, 2]);
+        reservations.set_stay(vec![2, 2, 1]);
+        let problem = Problem::new(cottages, reservations, 0);
+
+        let config = SolverConfig {
+            iterations: 100,
+            seed: 1,
+            ..Default::default()
+        };
+        let error = run_simulated_annealing(problem.clone(), 0, &config).unwrap_err();
+        assert_eq!(error.reservations.len(), 1);
+        assert!(error.diagnoses(&problem)[0].exclusions.is_empty());
+        assert!(error
+            .describe(&problem)
+            .ends_with("could not be placed; no allowed cottage is free"));
+
+        let mut problem = problem;
+        problem.allow_unallocated = true;
+        let instance = run_simulated_annealing(problem, 0, &config).unwrap();
+        assert_eq!(instance.solution.unallocated().len(), 1);
+    }
+
+    #[test]
+    fn test_criterion_cold() {
+        assert_eq!(criterion(0, 0f64), 1f64);
+        assert_eq!(criterion(5, 0f64), 0f64);
+        assert!(criterion(1, 1f64) > 0f64);
+    }
+
+    #[test]
+    fn test_thread_seed() {
+        assert_eq!(thread_seed(7, 0), thread_seed(7, 0));
+        assert_ne!(thread_seed(7, 0), thread_seed(7, 1));
+        assert_ne!(thread_seed(7, 0), thread_seed(8, 0));
+    }
 }
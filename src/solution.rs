@@ -1,8 +1,8 @@
-use std::ops::Range;
+use std::{collections::HashMap, ops::Range};
 
 use itertools::Itertools;
 
-use crate::problem::Problem;
+use crate::{allocation_penalty::Penalty, problem::Problem, violation::Violation};
 
 #[derive(Debug)]
 pub struct Solution {
@@ -13,55 +13,93 @@ pub struct Solution {
     gap_timetable: Vec<usize>,
     cottages: usize,
     timeslots: usize,
+    // Only days inside the horizon are tracked in the gap timetable,
+    // days outside it are 0 as if they were taken
+    horizon: Range<usize>,
     unallocated: Vec<usize>,
 }
 
 impl Solution {
     pub fn empty(problem: &Problem) -> Self {
         let reservations = problem.reservations.reservations();
-        let timeslots = problem.reservations.timeslots();
+        let timeslots = problem.timeslots();
         let cottages = problem.cottages.cottages();
+        let horizon = problem.horizon();
+
+        // Blocked days are taken from the start, so gaps end at a block
+        let gap_timetable = (0..cottages)
+            .flat_map(|cottage| {
+                gap_lengths(horizon.clone(), timeslots, |i| {
+                    problem.cottages.is_blocked(cottage, i..i + 1)
+                })
+            })
+            .collect();
 
         Self {
             mapping: vec![None; reservations],
             timetable: vec![None; cottages * timeslots],
-            gap_timetable: vec![timeslots; cottages * timeslots],
+            gap_timetable,
             cottages,
             timeslots,
+            horizon,
             unallocated: (0..reservations).collect(),
         }
     }
 
     pub fn naive(problem: &Problem) -> Self {
         let reservations = problem.reservations.reservations();
-        let timeslots = problem.reservations.timeslots();
-        let cottages = problem.cottages.cottages();
-
-        let mut out = Self {
-            mapping: vec![None; reservations],
-            timetable: vec![None; cottages * timeslots],
-            gap_timetable: vec![timeslots; cottages * timeslots],
-            cottages,
-            timeslots,
-            unallocated: (0..reservations).collect(),
-        };
+        let mut out = Self::empty(problem);
 
         for reservation in 0..reservations {
             out.assign(
                 reservation,
                 reservation,
-                problem.reservations.range(reservation),
+                problem.occupied(reservation, reservation),
             );
         }
 
         out
     }
 
+    // Start from cottage IDs of an earlier solution, in reservation order.
+    // Assignments that are unknown, no longer allowed or collide with an
+    // earlier one are left unallocated, to be repaired by the solver.
+    pub fn warm_start(problem: &Problem, ids: &[Option<usize>]) -> Self {
+        let mut solution = Self::empty(problem);
+        let cottage_index: HashMap<_, _> = problem
+            .cottages
+            .id
+            .iter()
+            .enumerate()
+            .map(|(index, &id)| (id, index))
+            .collect();
+
+        let reservations = problem.reservations.reservations();
+        for (reservation, id) in ids.iter().enumerate().take(reservations) {
+            let Some(&cottage) = id.and_then(|id| cottage_index.get(&id)) else {
+                continue;
+            };
+
+            let range = problem.occupied(reservation, cottage);
+            if problem.allocation_penalty.get(cottage, reservation) != Penalty::Impossible
+                && solution.is_free(cottage, range.clone())
+            {
+                solution.assign(cottage, reservation, range);
+            }
+        }
+
+        solution
+    }
+
+    pub fn horizon(&self) -> Range<usize> {
+        self.horizon.clone()
+    }
+
     pub fn unallocated(&self) -> &[usize] {
         &self.unallocated[..]
     }
 
-    pub fn index<'a>(&'a self, v: &'a Vec<usize>) -> impl Iterator<Item = Option<usize>> + 'a {
+    pub fn index<'a>(&'a self, v: &'a [usize]) -> impl Iterator<Item = Option<usize>> + 'a {
         #[cfg(feature = "supersafe")]
         assert!(v.len() >= self.reservations);
 
@@ -138,7 +176,11 @@ impl Solution {
         self.unallocated.retain(|x| *x != reservation);
         self.set_mapping(reservation, Some(cottage));
         self.set_timetable(cottage, Some(reservation), range.clone());
-        self.gap_timetable_assign(cottage, range);
+
+        let range = self.clip(range);
+        if !range.is_empty() {
+            self.gap_timetable_assign(cottage, range);
+        }
     }
 
     pub fn unassign(&mut self, cottage: usize, reservation: usize, range: Range<usize>) {
@@ -146,7 +188,17 @@ impl Solution {
         self.unallocated.push(reservation);
         self.set_mapping(reservation, None);
         self.set_timetable(cottage, None, range.clone());
-        self.gap_timetable_unassign(cottage, range);
+
+        let range = self.clip(range);
+        if !range.is_empty() {
+            self.gap_timetable_unassign(cottage, range);
+        }
+    }
+
+    // The part of a stay that lies inside the horizon
+    #[inline(always)]
+    fn clip(&self, range: Range<usize>) -> Range<usize> {
+        range.start.max(self.horizon.start)..range.end.min(self.horizon.end)
     }
 
     fn set_mapping(&mut self, reservation: usize, target: Option<usize>) {
@@ -189,7 +241,13 @@ impl Solution {
 
         // TODO: Pre-calculate this?
         let last_index = range.end + end.iter().take_while(|x| *x == gap).count();
-        let first_index = last_index - gap;
+        let first_index = last_index.wrapping_sub(*gap);
+        // Only free days can be taken, a blocked or taken day has no gap to split
+        assert!(
+            first_index <= range.start,
+            "cottage {cottage} is not free on day {}",
+            range.start
+        );
 
         #[cfg(feature = "supersafe")]
         {
@@ -242,12 +300,313 @@ impl Solution {
             v.get_unchecked_mut(range).fill(target);
         }
     }
+
+    // Full feasibility check, independent of how the solution was built
+    pub fn validate(&self, problem: &Problem) -> Result<(), Vec<Violation>> {
+        let mut violations = Vec::new();
+        self.validate_unallocated(&mut violations);
+        self.validate_timetables(problem, &mut violations);
+        self.validate_overlaps(problem, &mut violations);
+        self.validate_assignments(problem, &mut violations);
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    // `unallocated` holds exactly the reservations without a cottage
+    fn validate_unallocated(&self, violations: &mut Vec<Violation>) {
+        let mut listed = vec![0usize; self.mapping.len()];
+        for &reservation in &self.unallocated {
+            match listed.get_mut(reservation) {
+                Some(count) => *count += 1,
+                None => violations.push(Violation::Unallocated { reservation }),
+            }
+        }
+
+        for (reservation, cottage) in self.mapping.iter().enumerate() {
+            let expected = cottage.is_none() as usize;
+            if listed[reservation] != expected {
+                violations.push(Violation::Unallocated { reservation });
+            }
+        }
+    }
+
+    // The timetable matches the mapping, the gap timetable matches the timetable
+    fn validate_timetables(&self, problem: &Problem, violations: &mut Vec<Violation>) {
+        let mut expected = vec![None; self.timetable.len()];
+        for (reservation, cottage) in self.mapping.iter().enumerate() {
+            if let Some(cottage) = cottage {
+                let offset = cottage * self.timeslots;
+                let range = problem.occupied(reservation, *cottage);
+                expected[offset + range.start..offset + range.end].fill(Some(reservation));
+            }
+        }
+
+        for cottage in 0..self.cottages {
+            let timetable = self.get_timetable(cottage);
+            let expected = &expected[cottage * self.timeslots..(cottage + 1) * self.timeslots];
+            if let Some(timeslot) = (0..self.timeslots).find(|&i| timetable[i] != expected[i]) {
+                violations.push(Violation::Timetable { cottage, timeslot });
+            }
+
+            let gap_timetable = self.get_gap_timetable(cottage);
+            let expected = gap_lengths(self.horizon.clone(), self.timeslots, |i| {
+                timetable[i].is_some() || problem.cottages.is_blocked(cottage, i..i + 1)
+            });
+            if let Some(timeslot) = (0..self.timeslots).find(|&i| gap_timetable[i] != expected[i]) {
+                violations.push(Violation::GapTimetable { cottage, timeslot });
+            }
+        }
+    }
+
+    // No two reservations share a cottage on the same day
+    fn validate_overlaps(&self, problem: &Problem, violations: &mut Vec<Violation>) {
+        let mut per_cottage = vec![Vec::new(); self.cottages];
+        for (reservation, cottage) in self.mapping.iter().enumerate() {
+            if let Some(cottage) = cottage {
+                per_cottage[*cottage].push(reservation);
+            }
+        }
+
+        for (cottage, reservations) in per_cottage.iter_mut().enumerate() {
+            reservations.sort_by_key(|&x| problem.reservations.arrival()[x]);
+
+            // Compare with the reservation that departs last so far,
+            // a long stay can overlap more than its direct successor
+            let turnover = problem.cottages.turnover[cottage];
+            let mut last: Option<usize> = None;
+            for &reservation in reservations.iter() {
+                if let Some(other) = last {
+                    let departure = problem.reservations.departure()[other];
+                    let arrival = problem.reservations.arrival()[reservation];
+                    if departure > arrival {
+                        violations.push(Violation::Overlap {
+                            reservation,
+                            other,
+                            cottage,
+                        });
+                    } else if departure + turnover > arrival {
+                        violations.push(Violation::Turnover {
+                            reservation,
+                            other,
+                            cottage,
+                        });
+                    }
+                }
+                if last.is_none_or(|other| {
+                    problem.reservations.departure()[reservation]
+                        > problem.reservations.departure()[other]
+                }) {
+                    last = Some(reservation);
+                }
+            }
+        }
+    }
+
+    // Every reservation is assigned to a cottage it may use
+    fn validate_assignments(&self, problem: &Problem, violations: &mut Vec<Violation>) {
+        for (reservation, cottage) in self.mapping.iter().enumerate() {
+            let Some(cottage) = *cottage else {
+                if !problem.allow_unallocated {
+                    violations.push(Violation::Unassigned { reservation });
+                }
+                continue;
+            };
+
+            match problem.reservations.cottage_number[reservation] {
+                Some(fixed) if fixed != cottage => violations.push(Violation::FixedCottage {
+                    reservation,
+                    cottage,
+                    fixed,
+                }),
+                _ if problem
+                    .cottages
+                    .is_blocked(cottage, problem.occupied(reservation, cottage)) =>
+                {
+                    violations.push(Violation::Blocked {
+                        reservation,
+                        cottage,
+                    })
+                }
+                _ if problem.allocation_penalty.get(cottage, reservation)
+                    == Penalty::Impossible =>
+                {
+                    violations.push(Violation::Impossible {
+                        reservation,
+                        cottage,
+                    })
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+// Length of the free run each day is part of.
+// Taken days and days outside the horizon are 0.
+fn gap_lengths(
+    horizon: Range<usize>,
+    timeslots: usize,
+    taken: impl Fn(usize) -> bool,
+) -> Vec<usize> {
+    let mut gaps = vec![0; timeslots];
+    let mut start = horizon.start;
+    for i in horizon.clone() {
+        if taken(i) {
+            start = i + 1;
+        } else if i + 1 == horizon.end || taken(i + 1) {
+            gaps[start..i + 1].fill(i + 1 - start);
+        }
+    }
+    gaps
 }
 
 #[cfg(test)]
 mod test {
+    use crate::problem::{Cottages, Reservations};
+
     use super::*;
 
+    #[test]
+    pub fn warm_start() {
+        let mut cottages = Cottages::empty(3);
+        let mut reservations = Reservations::empty(4);
+        cottages.id = vec![1, 2, 3];
+        cottages.class = vec![1, 1, 0];
+        reservations.class = vec![1, 1, 1, 1];
+        let problem = Problem::new(cottages, reservations, 0);
+
+        // Reservation 1 collides with 0, 2 is no longer allowed in cottage 3,
+        // 3 is new
+        let solution = Solution::warm_start(&problem, &[Some(1), Some(1), Some(3)]);
+        assert_eq!(solution.mapping(), &[Some(0), None, None, None]);
+        assert_eq!(solution.unallocated(), &[1, 2, 3]);
+        assert_eq!(
+            solution.validate(&problem),
+            Err(vec![
+                Violation::Unassigned { reservation: 1 },
+                Violation::Unassigned { reservation: 2 },
+                Violation::Unassigned { reservation: 3 },
+            ])
+        );
+    }
+
+    #[test]
+    pub fn validate_valid() {
+        let problem = Problem::empty(3, 3);
+        let solution = Solution::naive(&problem);
+        assert_eq!(solution.validate(&problem), Ok(()));
+    }
+
+    #[test]
+    pub fn validate_unassigned() {
+        let problem = Problem::empty(3, 2);
+        let mut solution = Solution::naive(&problem);
+        solution.unassign(1, 1, 0..1);
+        assert_eq!(
+            solution.validate(&problem),
+            Err(vec![Violation::Unassigned { reservation: 1 }])
+        );
+    }
+
+    #[test]
+    pub fn validate_overlap() {
+        let mut reservations = Reservations::empty(3);
+        reservations.set_arrival(vec![0, 4, 2]);
+        reservations.set_stay(vec![6, 1, 1]);
+        let problem = Problem::new(Cottages::empty(2), reservations, 0);
+
+        let mut solution = Solution::empty(&problem);
+        solution.mapping = vec![Some(0), Some(0), Some(1)];
+        solution.unallocated.clear();
+
+        let violations = solution.validate(&problem).unwrap_err();
+        assert!(violations.contains(&Violation::Overlap {
+            reservation: 1,
+            other: 0,
+            cottage: 0
+        }));
+        assert!(violations.contains(&Violation::Timetable {
+            cottage: 0,
+            timeslot: 0
+        }));
+    }
+
+    #[test]
+    pub fn validate_horizon() {
+        let mut reservations = Reservations::empty(3);
+        reservations.set_arrival(vec![0, 3, 5]);
+        reservations.set_stay(vec![2, 2, 6]);
+        let mut problem = Problem::new(Cottages::empty(1), reservations, 0);
+        problem.horizon = Some(1..8);
+
+        // Stays keep their full length, the gap timetable covers days 1..8
+        let mut solution = Solution::empty(&problem);
+        for reservation in 0..3 {
+            solution.assign(0, reservation, problem.reservations.range(reservation));
+        }
+        assert_eq!(solution.timeslots, 11);
+        assert_eq!(
+            solution.get_gap_timetable(0),
+            &[0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(solution.validate(&problem), Ok(()));
+
+        solution.unassign(0, 2, 5..11);
+        assert_eq!(
+            solution.get_gap_timetable(0),
+            &[0, 0, 1, 0, 0, 3, 3, 3, 0, 0, 0]
+        );
+        solution.unassign(0, 0, 0..2);
+        assert_eq!(
+            solution.get_gap_timetable(0),
+            &[0, 2, 2, 0, 0, 3, 3, 3, 0, 0, 0]
+        );
+
+        solution.assign(0, 0, 0..2);
+        solution.assign(0, 2, 5..11);
+        assert_eq!(solution.validate(&problem), Ok(()));
+    }
+
+    #[test]
+    pub fn validate_inconsistent() {
+        let problem = Problem::empty(2, 2);
+        let mut solution = Solution::naive(&problem);
+        solution.unallocated.push(0);
+        solution.gap_timetable[0] = 1;
+
+        assert_eq!(
+            solution.validate(&problem),
+            Err(vec![
+                Violation::Unallocated { reservation: 0 },
+                Violation::GapTimetable {
+                    cottage: 0,
+                    timeslot: 0
+                }
+            ])
+        );
+    }
+
+    #[test]
+    pub fn validate_fixed_cottage() {
+        let mut reservations = Reservations::empty(1);
+        reservations.cottage_number = vec![Some(1)];
+        let problem = Problem::new(Cottages::empty(2), reservations, 0);
+        let solution = Solution::naive(&problem);
+
+        assert_eq!(
+            solution.validate(&problem),
+            Err(vec![Violation::FixedCottage {
+                reservation: 0,
+                cottage: 0,
+                fixed: 1
+            }])
+        );
+    }
+
     #[test]
     pub fn timetable_independent() {
         let size = 5;
@@ -261,7 +620,7 @@ mod test {
     pub fn assign_then_unassign_0() {
         let size = 5;
         let mut problem = Problem::empty(size, size);
-        problem.reservations.stay = vec![10; size];
+        problem.reservations.set_stay(vec![10; size]);
         let mut solution = Solution::empty(&problem);
 
         // __________
@@ -288,7 +647,7 @@ mod test {
     pub fn assign_then_unassign_1() {
         let size = 5;
         let mut problem = Problem::empty(size, size);
-        problem.reservations.stay = vec![1; size];
+        problem.reservations.set_stay(vec![1; size]);
         let mut solution = Solution::empty(&problem);
 
         // _
@@ -309,7 +668,7 @@ mod test {
     pub fn is_free() {
         let size = 5;
         let mut problem = Problem::empty(size, size);
-        problem.reservations.stay = vec![10; 5];
+        problem.reservations.set_stay(vec![10; 5]);
         let mut solution = Solution::empty(&problem);
         assert!(solution.is_free(0, 0..5));
         assert!(solution.is_free(1, 0..5));
@@ -326,7 +685,7 @@ mod test {
     pub fn is_taken_by() {
         let size = 5;
         let mut problem = Problem::empty(size, size);
-        problem.reservations.stay = vec![10; 5];
+        problem.reservations.set_stay(vec![10; 5]);
         let mut solution = Solution::empty(&problem);
 
         assert!(solution.is_taken_by(0, 0..10).collect_vec().is_empty());
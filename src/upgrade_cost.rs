@@ -1,4 +1,4 @@
-use crate::{allocation_penalty::Penalty, problem::Problem, solution::Solution};
+use crate::{problem::Problem, solution::Solution};
 
 // pub fn calculate_upgrades2(problem: &Problem, solution: &Solution) -> usize {
 //     let reservation_people = problem.reservations.people.iter();
@@ -42,15 +42,18 @@ pub fn calculate_upgrades(problem: &Problem, solution: &Solution) -> usize {
         .filter_map(|(reservation, cottage)| {
             cottage.map(|cottage| problem.allocation_penalty.get(cottage, reservation))
         })
-        .filter(|&x| x == Penalty::Upgrade)
-        .count()
+        .map(|x| x.upgrade_cost())
+        .sum()
 }
 
 pub fn calculate_upgrade(problem: &Problem, solution: &Solution, reservation: usize) -> usize {
     let cottage = solution.mapping()[reservation];
     cottage
         .map(|cottage| {
-            (problem.allocation_penalty.get(cottage, reservation) == Penalty::Upgrade) as usize
+            problem
+                .allocation_penalty
+                .get(cottage, reservation)
+                .upgrade_cost()
         })
         .unwrap_or_default()
 }
@@ -89,11 +92,12 @@ mod test {
         let problem = Problem::new(cottages, reservations, 0);
         let solution = Solution::naive(&problem);
 
+        // Three classes up
         let upgrade_cost = calculate_upgrades(&problem, &solution);
-        assert_eq!(upgrade_cost, 1);
+        assert_eq!(upgrade_cost, 3);
 
         let upgrade_cost = calculate_upgrade(&problem, &solution, 0);
-        assert_eq!(upgrade_cost, 1);
+        assert_eq!(upgrade_cost, 3);
     }
 
     #[test]
@@ -108,10 +112,10 @@ mod test {
         let solution = Solution::naive(&problem);
 
         let upgrade_cost = calculate_upgrades(&problem, &solution);
-        assert_eq!(upgrade_cost, 2);
+        assert_eq!(upgrade_cost, 4);
 
         let upgrade_cost = calculate_upgrade(&problem, &solution, 0);
-        assert_eq!(upgrade_cost, 1);
+        assert_eq!(upgrade_cost, 3);
 
         let upgrade_cost = calculate_upgrade(&problem, &solution, 1);
         assert_eq!(upgrade_cost, 1);
@@ -167,4 +171,27 @@ mod test {
         let capacity_cost = calculate_upgrades(&problem, &solution);
         assert_eq!(capacity_cost, 2);
     }
+    #[test]
+    fn test_class_and_capacity_cost() {
+        let size = 2;
+
+        let mut cottages = Cottages::empty(size);
+        let mut reservations = Reservations::empty(size);
+        cottages.capacity = vec![2, 12];
+        cottages.class = vec![1, 4];
+        reservations.people = vec![2, 2];
+        reservations.class = vec![1, 1];
+        reservations.set_arrival(vec![0, 1]);
+
+        let problem = Problem::new(cottages, reservations, 0);
+        let mut solution = Solution::naive(&problem);
+
+        // One capacity step and three classes up
+        assert_eq!(calculate_upgrade(&problem, &solution, 1), 4);
+        assert_eq!(calculate_upgrades(&problem, &solution), 4);
+
+        solution.unassign(1, 1, problem.reservations.range(1));
+        solution.assign(0, 1, problem.reservations.range(1));
+        assert_eq!(calculate_upgrades(&problem, &solution), 0);
+    }
 }
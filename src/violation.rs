@@ -0,0 +1,143 @@
+use crate::problem::Problem;
+
+// A broken constraint in a solution.
+// Reservations and cottages are indices into the problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    // The solution file names a cottage ID that does not exist
+    UnknownCottage {
+        reservation: usize,
+        id: usize,
+    },
+    // The solution file has a different number of lines than there are reservations
+    LineCount {
+        expected: usize,
+        found: usize,
+    },
+    Unassigned {
+        reservation: usize,
+    },
+    Overlap {
+        reservation: usize,
+        other: usize,
+        cottage: usize,
+    },
+    // `reservation` arrives before the turnover days after `other` are over
+    Turnover {
+        reservation: usize,
+        other: usize,
+        cottage: usize,
+    },
+    // The cottage is ruled out by preferences, class or size
+    Impossible {
+        reservation: usize,
+        cottage: usize,
+    },
+    FixedCottage {
+        reservation: usize,
+        cottage: usize,
+        fixed: usize,
+    },
+    // The stay or its turnover days overlap a block of the cottage
+    Blocked {
+        reservation: usize,
+        cottage: usize,
+    },
+    // The unallocated list disagrees with the mapping for this reservation
+    Unallocated {
+        reservation: usize,
+    },
+    // The timetable disagrees with the mapping from this timeslot on
+    Timetable {
+        cottage: usize,
+        timeslot: usize,
+    },
+    // The gap timetable disagrees with the timetable at this timeslot
+    GapTimetable {
+        cottage: usize,
+        timeslot: usize,
+    },
+}
+
+impl Violation {
+    pub fn describe(&self, problem: &Problem) -> String {
+        let reservation_id = |x: usize| problem.reservations.id[x];
+        let cottage_id = |x: usize| problem.cottages.id[x];
+
+        match *self {
+            Violation::UnknownCottage { reservation, id } => format!(
+                "Reservation {} is assigned to unknown cottage {}",
+                reservation_id(reservation),
+                id
+            ),
+            Violation::LineCount { expected, found } => format!(
+                "Solution has {} lines, expected one per reservation ({})",
+                found, expected
+            ),
+            Violation::Unassigned { reservation } => format!(
+                "Reservation {} is not assigned",
+                reservation_id(reservation)
+            ),
+            Violation::Overlap {
+                reservation,
+                other,
+                cottage,
+            } => format!(
+                "Reservation {} overlaps reservation {} in cottage {}",
+                reservation_id(reservation),
+                reservation_id(other),
+                cottage_id(cottage)
+            ),
+            Violation::Turnover {
+                reservation,
+                other,
+                cottage,
+            } => format!(
+                "Reservation {} arrives in cottage {} before the turnover after reservation {} is done",
+                reservation_id(reservation),
+                cottage_id(cottage),
+                reservation_id(other)
+            ),
+            Violation::Impossible {
+                reservation,
+                cottage,
+            } => format!(
+                "Reservation {} can not be assigned to cottage {}",
+                reservation_id(reservation),
+                cottage_id(cottage)
+            ),
+            Violation::FixedCottage {
+                reservation,
+                cottage,
+                fixed,
+            } => format!(
+                "Reservation {} is fixed to cottage {} but assigned to cottage {}",
+                reservation_id(reservation),
+                cottage_id(fixed),
+                cottage_id(cottage)
+            ),
+            Violation::Blocked {
+                reservation,
+                cottage,
+            } => format!(
+                "Reservation {} overlaps a block of cottage {}",
+                reservation_id(reservation),
+                cottage_id(cottage)
+            ),
+            Violation::Unallocated { reservation } => format!(
+                "Reservation {} is listed as unallocated incorrectly",
+                reservation_id(reservation)
+            ),
+            Violation::Timetable { cottage, timeslot } => format!(
+                "Timetable of cottage {} disagrees with the mapping at timeslot {}",
+                cottage_id(cottage),
+                timeslot
+            ),
+            Violation::GapTimetable { cottage, timeslot } => format!(
+                "Gap timetable of cottage {} is wrong at timeslot {}",
+                cottage_id(cottage),
+                timeslot
+            ),
+        }
+    }
+}